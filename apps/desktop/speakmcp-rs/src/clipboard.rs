@@ -0,0 +1,68 @@
+//! Clipboard-paste text injection
+//! Puts the text on the system clipboard, presses the platform paste shortcut
+//! and then restores the previous clipboard contents. Much faster than typing
+//! long transcripts key by key, and immune to apps that drop synthetic keys.
+
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+/// Give the clipboard ownership change time to settle before pasting
+const BEFORE_PASTE_DELAY: Duration = Duration::from_millis(50);
+
+/// Give the target app time to read the clipboard before it's restored
+const BEFORE_RESTORE_DELAY: Duration = Duration::from_millis(300);
+
+/// Clipboard contents saved before pasting
+enum Previous {
+    Text(String),
+    Files(Vec<PathBuf>),
+    /// Empty, or something arboard can't read back such as an image
+    Unknown,
+}
+
+fn save(clipboard: &mut arboard::Clipboard) -> Previous {
+    if let Ok(text) = clipboard.get_text() {
+        return Previous::Text(text);
+    }
+    match clipboard.get().file_list() {
+        Ok(files) if !files.is_empty() => Previous::Files(files),
+        _ => Previous::Unknown,
+    }
+}
+
+pub fn paste_text(text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut clipboard = arboard::Clipboard::new()
+        .map_err(|e| format!("Failed to open clipboard: {}", e))?;
+
+    // Text and file lists are restored afterwards; images etc. are not preserved
+    let previous = save(&mut clipboard);
+
+    clipboard
+        .set_text(text)
+        .map_err(|e| format!("Failed to set clipboard text: {}", e))?;
+    thread::sleep(BEFORE_PASTE_DELAY);
+
+    let result = send_paste_shortcut();
+    thread::sleep(BEFORE_RESTORE_DELAY);
+
+    // Restore failures are logged but don't fail the write - the text was pasted
+    let restored = match previous {
+        Previous::Text(previous) => clipboard.set_text(previous),
+        Previous::Files(files) => clipboard.set().file_list(&files),
+        // An empty clipboard can't be told apart from an image here, so the
+        // transcript is left in place rather than clearing the clipboard
+        Previous::Unknown => Ok(()),
+    };
+    if let Err(e) = restored {
+        eprintln!("Failed to restore clipboard: {}", e);
+    }
+
+    result
+}
+
+/// Press Cmd+V on macOS, Ctrl+V elsewhere
+fn send_paste_shortcut() -> Result<(), Box<dyn std::error::Error>> {
+    let shortcut = if cfg!(target_os = "macos") { "cmd+v" } else { "ctrl+v" };
+    crate::press::press_combo(&crate::press::parse_combo(shortcut)?)
+}
@@ -0,0 +1,23 @@
+//! Unicode entry via Ctrl+Shift+U on Linux
+//! GTK and IBus/Fcitx input methods turn Ctrl+Shift+U, hex digits and Space
+//! into that character. It's the last resort for text that neither keysym
+//! remapping nor the clipboard could get into the focused app.
+
+use crate::press;
+use enigo::{Enigo, Keyboard, Settings};
+
+/// Type `text`, entering non-ASCII characters as Ctrl+Shift+U sequences
+pub fn type_text(text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let unicode_entry = press::parse_combo("ctrl+shift+u")?;
+    let mut enigo = Enigo::new(&Settings::default())?;
+    for c in text.chars() {
+        if c.is_ascii() {
+            enigo.text(c.encode_utf8(&mut [0; 4]))?;
+        } else {
+            press::press_combo(&unicode_entry)?;
+            // Space commits the code point
+            enigo.text(&format!("{:x} ", c as u32))?;
+        }
+    }
+    Ok(())
+}
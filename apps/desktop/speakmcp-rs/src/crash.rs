@@ -0,0 +1,134 @@
+//! Crash reports for panics
+//! A panic in a listener thread or serve mode otherwise only shows up on
+//! stderr, which the desktop app rarely keeps. The panic hook writes a JSON
+//! report to the app's data folder and reports its path as an Error event.
+//!
+//! Reports include the last few protocol messages, reduced to their type and
+//! size: key names and dictated text never end up on disk.
+
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How many recent protocol messages a report includes
+const RECENT_MESSAGE_COUNT: usize = 32;
+
+static RECENT_MESSAGES: Mutex<VecDeque<Value>> = Mutex::new(VecDeque::new());
+
+/// Remember a protocol message for crash reports, without its payload
+/// `direction` is "in" for stdin commands and "out" for emitted events
+pub fn record_message(direction: &str, message_type: &str, payload_bytes: usize) {
+    let Ok(mut messages) = RECENT_MESSAGES.lock() else {
+        return;
+    };
+    if messages.len() == RECENT_MESSAGE_COUNT {
+        messages.pop_front();
+    }
+    messages.push_back(json!({
+        "direction": direction,
+        "type": message_type,
+        "payload_bytes": payload_bytes,
+        "time_ms": SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64),
+    }));
+}
+
+/// Where crash reports go: `crashes` in the desktop app's data folder
+/// (Electron's appData + APP_ID; APP_ID is a build-time define in the app,
+/// which keyboard.ts passes to the helper's environment explicitly)
+pub fn crashes_dir() -> Option<PathBuf> {
+    let app_data = if cfg!(target_os = "windows") {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    }?;
+    let app_id = std::env::var("APP_ID").unwrap_or_else(|_| "app.speakmcp".to_string());
+    Some(app_data.join(app_id).join("crashes"))
+}
+
+fn write_report(report: &Value) -> std::io::Result<PathBuf> {
+    let dir = crashes_dir().ok_or_else(|| std::io::Error::other("No home directory for crash reports"))?;
+    std::fs::create_dir_all(&dir)?;
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let path = dir.join(format!("speakmcp-rs-{}-{}.json", time, std::process::id()));
+    std::fs::write(&path, serde_json::to_string_pretty(report).unwrap_or_default())?;
+    Ok(path)
+}
+
+/// Install the panic hook; the default hook still prints the panic to stderr
+pub fn install() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let message = info
+            .payload()
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| info.payload().downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        // try_lock: the panic may have happened while recording a message
+        let recent: Vec<Value> = RECENT_MESSAGES
+            .try_lock()
+            .map(|messages| messages.iter().cloned().collect())
+            .unwrap_or_default();
+
+        let report = json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "os": std::env::consts::OS,
+            "arch": std::env::consts::ARCH,
+            "command": std::env::args().nth(1),
+            "thread": std::thread::current().name().unwrap_or("unnamed"),
+            "message": message,
+            "location": info.location().map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column())),
+            "time": SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+            "backtrace": std::backtrace::Backtrace::force_capture().to_string(),
+            "recent_messages": recent,
+        });
+
+        default_hook(info);
+        match write_report(&report) {
+            Ok(path) => {
+                let message = format!("speakmcp-rs crashed, report written to {}", path.display());
+                // Straight to stdout: output_event records the message and writes the
+                // event log, and the panic may have happened holding either lock
+                crate::output::emit(&crate::error_event("Crash", &message));
+                crate::output::flush();
+                eprintln!("!error: Crash - {}", message);
+            }
+            Err(e) => eprintln!("!error: failed to write crash report: {}", e),
+        }
+    }));
+}
+
+/// Summaries of the saved crash reports, newest first
+pub fn list_reports() -> Vec<Value> {
+    let Some(dir) = crashes_dir() else {
+        return Vec::new();
+    };
+    let Ok(entries) = std::fs::read_dir(&dir) else {
+        return Vec::new();
+    };
+
+    let mut reports: Vec<Value> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|path| {
+            let report: Value = serde_json::from_str(&std::fs::read_to_string(&path).ok()?).ok()?;
+            Some(json!({
+                "path": path.display().to_string(),
+                "time": report["time"],
+                "version": report["version"],
+                "message": report["message"],
+                "location": report["location"],
+            }))
+        })
+        .collect();
+    reports.sort_by_key(|report| std::cmp::Reverse(report["time"].as_u64().unwrap_or(0)));
+    reports
+}
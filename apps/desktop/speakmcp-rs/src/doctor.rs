@@ -0,0 +1,257 @@
+//! `doctor`: report what would stop the helper from working, as JSON
+//! Missing permissions otherwise only show up as a listener that silently
+//! produces nothing, so the desktop app runs this during onboarding and shows
+//! the failed checks with their messages.
+
+use serde::Serialize;
+use serde_json::json;
+
+#[derive(Serialize)]
+struct Check {
+    name: &'static str,
+    ok: bool,
+    /// Whether listening or typing can't work at all while this fails
+    required: bool,
+    message: String,
+}
+
+impl Check {
+    fn new(name: &'static str, ok: bool, required: bool, message: impl Into<String>) -> Self {
+        Check { name, ok, required, message: message.into() }
+    }
+}
+
+// ============ Linux: /dev/input access ============
+
+/// Groups listed for `user` in /etc/group, which may not be active in this
+/// session yet if they were just added
+#[cfg(target_os = "linux")]
+fn configured_groups(user: &str) -> Vec<String> {
+    let contents = std::fs::read_to_string("/etc/group").unwrap_or_default();
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let group = fields.next()?;
+            let members = fields.nth(2)?;
+            members.split(',').any(|member| member == user).then(|| group.to_string())
+        })
+        .collect()
+}
+
+/// Whether this process is in the named group
+#[cfg(target_os = "linux")]
+fn in_group(name: &str) -> Option<bool> {
+    let name = std::ffi::CString::new(name).ok()?;
+    unsafe {
+        let group = libc::getgrnam(name.as_ptr());
+        if group.is_null() {
+            return None;
+        }
+        let gid = (*group).gr_gid;
+        if libc::getegid() == gid {
+            return Some(true);
+        }
+        let count = libc::getgroups(0, std::ptr::null_mut());
+        let mut groups = vec![0; count.max(0) as usize];
+        let count = libc::getgroups(groups.len() as libc::c_int, groups.as_mut_ptr());
+        Some(groups[..count.max(0) as usize].contains(&gid))
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn platform_checks() -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    let mut total = 0;
+    let mut readable = 0;
+    let mut keyboards = 0;
+    let mut denied = false;
+    for entry in std::fs::read_dir("/dev/input").into_iter().flatten().filter_map(|e| e.ok()) {
+        if !entry.file_name().to_string_lossy().starts_with("event") {
+            continue;
+        }
+        total += 1;
+        match evdev::Device::open(entry.path()) {
+            Ok(device) => {
+                readable += 1;
+                if crate::is_keyboard_device(&device) {
+                    keyboards += 1;
+                }
+            }
+            Err(e) => denied |= e.kind() == std::io::ErrorKind::PermissionDenied,
+        }
+    }
+    let message = if keyboards > 0 {
+        format!("{} of {} input devices readable, {} keyboard(s)", readable, total, keyboards)
+    } else if denied {
+        "Cannot read /dev/input/event*. Run: sudo usermod -aG input $USER, then log out and back in.".to_string()
+    } else {
+        "No keyboard found in /dev/input".to_string()
+    };
+    checks.push(Check::new("input_devices", keyboards > 0, true, message));
+
+    let user = std::env::var("USER").unwrap_or_default();
+    let check = match in_group("input") {
+        None => Check::new("input_group", false, false, "There is no 'input' group on this system"),
+        Some(true) => Check::new("input_group", true, false, "Process is in the 'input' group"),
+        Some(false) if configured_groups(&user).iter().any(|group| group == "input") => Check::new(
+            "input_group",
+            false,
+            false,
+            "User was added to the 'input' group, but this session predates it. Log out and back in.",
+        ),
+        Some(false) => Check::new(
+            "input_group",
+            false,
+            false,
+            "User is not in the 'input' group. Run: sudo usermod -aG input $USER, then log out and back in.",
+        ),
+    };
+    checks.push(check);
+
+    let uinput = std::fs::OpenOptions::new().write(true).open("/dev/uinput");
+    checks.push(match uinput {
+        Ok(_) => Check::new("uinput", true, false, "/dev/uinput is writable"),
+        Err(e) => Check::new(
+            "uinput",
+            false,
+            false,
+            format!("Cannot open /dev/uinput ({}); --grab and the uinput write backend won't work", e),
+        ),
+    });
+
+    let session = if crate::uinput::is_wayland_session() {
+        "wayland"
+    } else if std::env::var_os("DISPLAY").is_some() {
+        "x11"
+    } else {
+        "none"
+    };
+    checks.push(Check::new("display_server", true, false, format!("Session type: {}", session)));
+
+    checks
+}
+
+// ============ macOS: Accessibility and Input Monitoring ============
+
+#[cfg(target_os = "macos")]
+fn platform_checks() -> Vec<Check> {
+    // Both grants belong to the app that launched us, not to this binary
+    let crate::permissions::Permissions { accessibility, input_monitoring, .. } = crate::permissions::query();
+    vec![
+        Check::new(
+            "accessibility",
+            accessibility,
+            true,
+            if accessibility {
+                "Accessibility access granted"
+            } else {
+                "Grant Accessibility access in System Settings > Privacy & Security > Accessibility"
+            },
+        ),
+        Check::new(
+            "input_monitoring",
+            input_monitoring,
+            true,
+            if input_monitoring {
+                "Input Monitoring access granted"
+            } else {
+                "Grant Input Monitoring access in System Settings > Privacy & Security > Input Monitoring"
+            },
+        ),
+    ]
+}
+
+// ============ Windows: elevation and UIPI ============
+
+#[cfg(target_os = "windows")]
+mod win32 {
+    pub type Handle = *mut std::ffi::c_void;
+    pub const TOKEN_QUERY: u32 = 0x0008;
+    /// TOKEN_INFORMATION_CLASS::TokenElevation
+    pub const TOKEN_ELEVATION: i32 = 20;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        pub fn GetCurrentProcess() -> Handle;
+        pub fn CloseHandle(handle: Handle) -> i32;
+    }
+
+    #[link(name = "advapi32")]
+    extern "system" {
+        pub fn OpenProcessToken(process: Handle, access: u32, token: *mut Handle) -> i32;
+        pub fn GetTokenInformation(
+            token: Handle,
+            class: i32,
+            info: *mut std::ffi::c_void,
+            length: u32,
+            return_length: *mut u32,
+        ) -> i32;
+    }
+}
+
+/// Whether this process runs elevated (as administrator)
+#[cfg(target_os = "windows")]
+fn is_elevated() -> Option<bool> {
+    use win32::*;
+    unsafe {
+        let mut token: Handle = std::ptr::null_mut();
+        if OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) == 0 {
+            return None;
+        }
+        let mut elevation: u32 = 0;
+        let mut length = 0;
+        let ok = GetTokenInformation(
+            token,
+            TOKEN_ELEVATION,
+            &mut elevation as *mut u32 as *mut _,
+            std::mem::size_of::<u32>() as u32,
+            &mut length,
+        );
+        CloseHandle(token);
+        (ok != 0).then_some(elevation != 0)
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn platform_checks() -> Vec<Check> {
+    // UIPI drops input sent from a normal process to an elevated window, so
+    // typing into apps run as administrator fails without an error
+    let check = match is_elevated() {
+        Some(true) => Check::new("elevation", true, false, "Running elevated; can type into all apps"),
+        Some(false) => Check::new(
+            "elevation",
+            false,
+            false,
+            "Not elevated: typing into and hotkeys in apps running as administrator are blocked by UIPI",
+        ),
+        None => Check::new("elevation", false, false, "Could not determine process elevation"),
+    };
+    vec![check]
+}
+
+/// Parse and run `doctor [--crashes]`
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut crashes = false;
+    for arg in args {
+        match arg.as_str() {
+            "--crashes" => crashes = true,
+            _ => return Err(format!("Unknown doctor option '{}'", arg)),
+        }
+    }
+
+    if crashes {
+        let dir = crate::crash::crashes_dir().map(|dir| dir.display().to_string());
+        println!("{}", json!({"crashes_dir": dir, "crashes": crate::crash::list_reports()}));
+        return Ok(());
+    }
+
+    let checks = platform_checks();
+    let ok = checks.iter().all(|check| check.ok || !check.required);
+    println!(
+        "{}",
+        json!({"platform": std::env::consts::OS, "ok": ok, "checks": checks})
+    );
+    Ok(())
+}
@@ -0,0 +1,160 @@
+//! `listen --log-file`: a JSONL copy of every emitted event
+//! Lets hotkey misfires be analyzed after the fact without touching the
+//! consumer side. The file is rotated by size to `<path>.1`, `<path>.2`, ...
+
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Rotate once the log reaches this size unless `--rotate-size` says otherwise
+pub const DEFAULT_ROTATE_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Number of rotated files kept next to the live one
+const ROTATED_FILES: usize = 3;
+
+/// An open event log, ready to be installed
+pub struct EventLog {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    rotate_size: u64,
+}
+
+static LOG: Mutex<Option<EventLog>> = Mutex::new(None);
+
+/// Parse a size like "10M", "512K", "1G" or a plain byte count
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let (number, multiplier) = match value.char_indices().last() {
+        Some((i, 'K' | 'k')) => (&value[..i], 1024),
+        Some((i, 'M' | 'm')) => (&value[..i], 1024 * 1024),
+        Some((i, 'G' | 'g')) => (&value[..i], 1024 * 1024 * 1024),
+        _ => (value, 1),
+    };
+    let number = number
+        .parse::<u64>()
+        .ok()
+        .filter(|n| *n > 0)
+        .ok_or_else(|| format!("Invalid size '{}'", value))?;
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("Size '{}' is too large", value))
+}
+
+fn open(path: &PathBuf) -> std::io::Result<(File, u64)> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let size = file.metadata()?.len();
+    Ok((file, size))
+}
+
+/// Open the log at `path` without installing it; None for no log
+pub fn prepare(path: Option<&str>, rotate_size: Option<u64>) -> Result<Option<EventLog>, String> {
+    let Some(path) = path else {
+        return Ok(None);
+    };
+    let path = PathBuf::from(path);
+    let (file, size) = open(&path).map_err(|e| format!("Cannot open log file {}: {}", path.display(), e))?;
+    Ok(Some(EventLog { path, file, size, rotate_size: rotate_size.unwrap_or(DEFAULT_ROTATE_SIZE) }))
+}
+
+/// Start copying events to a prepared log, or stop with None
+pub fn install(log: Option<EventLog>) {
+    *LOG.lock().unwrap() = log;
+}
+
+impl EventLog {
+    /// Shift `<path>.N` up by one and start a fresh live file
+    fn rotate(&mut self) -> std::io::Result<()> {
+        let rotated = |n: usize| {
+            let mut name = self.path.clone().into_os_string();
+            name.push(format!(".{}", n));
+            PathBuf::from(name)
+        };
+        for n in (1..ROTATED_FILES).rev() {
+            let _ = std::fs::rename(rotated(n), rotated(n + 1));
+        }
+        std::fs::rename(&self.path, rotated(1))?;
+        (self.file, self.size) = open(&self.path)?;
+        Ok(())
+    }
+
+    fn write(&mut self, line: &str) -> std::io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.size > 0 && self.size + len > self.rotate_size {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.size += len;
+        Ok(())
+    }
+}
+
+/// Append an emitted message to the log as JSON, if a log is configured
+/// A failing log is dropped with a warning rather than stopping events
+pub fn write<T: Serialize>(message: &T) {
+    if LOG.lock().unwrap().is_none() {
+        return;
+    }
+    // Serialized before taking the lock again, so a panic here can't leave
+    // LOG held for the crash hook's own output
+    let line = serde_json::to_string(message).unwrap();
+    let mut log = LOG.lock().unwrap();
+    if let Some(event_log) = log.as_mut() {
+        if let Err(e) = event_log.write(&line) {
+            eprintln!("!error: event log {} stopped: {}", event_log.path.display(), e);
+            *log = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sizes_with_suffixes() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("512K"), Ok(512 * 1024));
+        assert_eq!(parse_size("10M"), Ok(10 * 1024 * 1024));
+        assert_eq!(parse_size("10m"), Ok(10 * 1024 * 1024));
+        assert_eq!(parse_size("1G"), Ok(1024 * 1024 * 1024));
+        assert_eq!(parse_size(" 2M "), Ok(2 * 1024 * 1024));
+    }
+
+    #[test]
+    fn rejects_zero_and_malformed_sizes() {
+        for size in ["0", "0M", "", "M", "10MB", "-1", "1.5M", "ten"] {
+            assert!(parse_size(size).is_err(), "{:?} should be rejected", size);
+        }
+    }
+
+    #[test]
+    fn rejects_sizes_that_overflow() {
+        assert_eq!(parse_size("99999999999G"), Err("Size '99999999999G' is too large".to_string()));
+        assert_eq!(parse_size("18014398509481984K"), Err("Size '18014398509481984K' is too large".to_string()));
+        assert_eq!(parse_size("17179869183G"), Ok(17179869183 * 1024 * 1024 * 1024));
+        assert!(parse_size("99999999999999999999").is_err());
+    }
+
+    #[test]
+    fn rotates_and_keeps_three_files() {
+        let dir = std::env::temp_dir().join(format!("speakmcp-rs-eventlog-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("events.jsonl");
+
+        // Each line is 10 bytes with its newline, so every other line rotates
+        let mut log = prepare(path.to_str(), Some(20)).unwrap().unwrap();
+        for n in 0..10 {
+            log.write(&format!("line {:04}", n)).unwrap();
+        }
+
+        let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("events.jsonl"), "line 0008\nline 0009\n");
+        assert_eq!(read("events.jsonl.1"), "line 0006\nline 0007\n");
+        assert_eq!(read("events.jsonl.3"), "line 0002\nline 0003\n");
+        assert!(!dir.join("events.jsonl.4").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
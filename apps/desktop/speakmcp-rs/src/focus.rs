@@ -0,0 +1,446 @@
+//! `focus`: report the focused application and window
+//! Gives the agent "where the user is typing" as context, and lets the app
+//! check for a password field before injecting a transcript into it.
+
+use serde::Serialize;
+
+#[derive(Serialize, Default)]
+pub struct FocusInfo {
+    /// Application name, bundle/app id or executable, whichever the platform offers
+    pub app: Option<String>,
+    /// Window title
+    pub title: Option<String>,
+    pub pid: Option<u32>,
+    /// Whether keyboard focus is in a password field; None where it can't be told
+    pub password_field: Option<bool>,
+    /// Which API answered: x11, wlr-foreign-toplevel, accessibility or win32
+    pub source: &'static str,
+}
+
+// ============ Linux: wlr-foreign-toplevel on Wayland, EWMH on X11 ============
+
+#[cfg(target_os = "linux")]
+mod wlr {
+    use super::FocusInfo;
+    use std::collections::HashMap;
+    use wayland_client::backend::ObjectId;
+    use wayland_client::globals::{registry_queue_init, GlobalListContents};
+    use wayland_client::protocol::wl_registry;
+    use wayland_client::{event_created_child, Connection, Dispatch, Proxy, QueueHandle};
+    use wayland_protocols_wlr::foreign_toplevel::v1::client::{
+        zwlr_foreign_toplevel_handle_v1::{self, ZwlrForeignToplevelHandleV1},
+        zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1},
+    };
+
+    #[derive(Default)]
+    struct Toplevel {
+        title: Option<String>,
+        app_id: Option<String>,
+        activated: bool,
+    }
+
+    #[derive(Default)]
+    struct State {
+        toplevels: HashMap<ObjectId, Toplevel>,
+    }
+
+    impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for State {
+        fn event(
+            _: &mut Self,
+            _: &wl_registry::WlRegistry,
+            _: wl_registry::Event,
+            _: &GlobalListContents,
+            _: &Connection,
+            _: &QueueHandle<Self>,
+        ) {
+        }
+    }
+
+    impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for State {
+        fn event(
+            state: &mut Self,
+            _: &ZwlrForeignToplevelManagerV1,
+            event: zwlr_foreign_toplevel_manager_v1::Event,
+            _: &(),
+            _: &Connection,
+            _: &QueueHandle<Self>,
+        ) {
+            if let zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } = event {
+                state.toplevels.insert(toplevel.id(), Toplevel::default());
+            }
+        }
+
+        event_created_child!(State, ZwlrForeignToplevelManagerV1, [
+            zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (ZwlrForeignToplevelHandleV1, ()),
+        ]);
+    }
+
+    impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for State {
+        fn event(
+            state: &mut Self,
+            handle: &ZwlrForeignToplevelHandleV1,
+            event: zwlr_foreign_toplevel_handle_v1::Event,
+            _: &(),
+            _: &Connection,
+            _: &QueueHandle<Self>,
+        ) {
+            use zwlr_foreign_toplevel_handle_v1::Event;
+
+            let toplevel = state.toplevels.entry(handle.id()).or_default();
+            match event {
+                Event::Title { title } => toplevel.title = Some(title),
+                Event::AppId { app_id } => toplevel.app_id = Some(app_id),
+                Event::State { state: states } => {
+                    let activated = zwlr_foreign_toplevel_handle_v1::State::Activated as u32;
+                    toplevel.activated = states
+                        .chunks_exact(4)
+                        .any(|value| u32::from_ne_bytes([value[0], value[1], value[2], value[3]]) == activated);
+                }
+                Event::Closed => {
+                    state.toplevels.remove(&handle.id());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// The activated toplevel, on compositors with wlr-foreign-toplevel-management (Sway, Hyprland, ...)
+    pub fn focused_window() -> Result<FocusInfo, Box<dyn std::error::Error>> {
+        let conn = Connection::connect_to_env()
+            .map_err(|e| format!("Cannot connect to Wayland compositor: {}", e))?;
+        let (globals, mut event_queue) = registry_queue_init::<State>(&conn)?;
+        let qh = event_queue.handle();
+        let _manager: ZwlrForeignToplevelManagerV1 = globals
+            .bind(&qh, 1..=3, ())
+            .map_err(|_| "Wayland compositor doesn't support wlr-foreign-toplevel-management")?;
+
+        // The first roundtrip announces the toplevels, the second delivers their state
+        let mut state = State::default();
+        event_queue.roundtrip(&mut state)?;
+        event_queue.roundtrip(&mut state)?;
+
+        let toplevel = state
+            .toplevels
+            .into_values()
+            .find(|toplevel| toplevel.activated)
+            .ok_or("No focused window")?;
+        Ok(FocusInfo {
+            app: toplevel.app_id,
+            title: toplevel.title,
+            source: "wlr-foreign-toplevel",
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod x11 {
+    use super::FocusInfo;
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xproto::{AtomEnum, ConnectionExt, Window};
+
+    /// Read a property as bytes, or None if it isn't set
+    fn property(
+        conn: &impl Connection,
+        window: Window,
+        property: impl Into<u32>,
+        kind: impl Into<u32>,
+    ) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+        let reply = conn.get_property(false, window, property, kind, 0, u32::MAX / 4)?.reply()?;
+        Ok((!reply.value.is_empty()).then_some(reply.value))
+    }
+
+    /// The window in _NET_ACTIVE_WINDOW, per EWMH
+    pub fn focused_window() -> Result<FocusInfo, Box<dyn std::error::Error>> {
+        let (conn, screen) = x11rb::connect(None).map_err(|e| format!("Cannot connect to X server: {}", e))?;
+        let root = conn.setup().roots[screen].root;
+        let atom = |name: &[u8]| -> Result<u32, Box<dyn std::error::Error>> {
+            Ok(conn.intern_atom(false, name)?.reply()?.atom)
+        };
+
+        let active = conn
+            .get_property(false, root, atom(b"_NET_ACTIVE_WINDOW")?, AtomEnum::WINDOW, 0, 1)?
+            .reply()?;
+        let window = active
+            .value32()
+            .and_then(|mut values| values.next())
+            .filter(|window| *window != 0)
+            .ok_or("No focused window")?;
+
+        let title = match property(&conn, window, atom(b"_NET_WM_NAME")?, atom(b"UTF8_STRING")?)? {
+            Some(title) => Some(title),
+            None => property(&conn, window, AtomEnum::WM_NAME, AtomEnum::STRING)?,
+        };
+        // WM_CLASS is "instance\0class\0"; the class is the application name
+        let app = property(&conn, window, AtomEnum::WM_CLASS, AtomEnum::STRING)?.and_then(|class| {
+            class.split(|b| *b == 0).nth(1).map(|name| String::from_utf8_lossy(name).into_owned())
+        });
+        let pid = conn
+            .get_property(false, window, atom(b"_NET_WM_PID")?, AtomEnum::CARDINAL, 0, 1)?
+            .reply()?
+            .value32()
+            .and_then(|mut values| values.next());
+
+        Ok(FocusInfo {
+            app,
+            title: title.map(|title| String::from_utf8_lossy(&title).into_owned()),
+            pid,
+            password_field: None,
+            source: "x11",
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn focused_window() -> Result<FocusInfo, Box<dyn std::error::Error>> {
+    if crate::uinput::is_wayland_session() {
+        match wlr::focused_window() {
+            Ok(info) => return Ok(info),
+            // GNOME and KDE don't offer the protocol; XWayland still knows about X apps
+            Err(e) if std::env::var_os("DISPLAY").is_some() => {
+                eprintln!("{}, trying X11", e);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    x11::focused_window()
+}
+
+// ============ macOS: Accessibility API ============
+
+#[cfg(target_os = "macos")]
+mod ax {
+    use super::FocusInfo;
+    use std::ffi::{c_char, c_void};
+
+    type CFTypeRef = *const c_void;
+
+    const K_CF_STRING_ENCODING_UTF8: u32 = 0x0800_0100;
+    const K_AX_ERROR_SUCCESS: i32 = 0;
+    const K_AX_ERROR_API_DISABLED: i32 = -25211;
+
+    #[link(name = "ApplicationServices", kind = "framework")]
+    extern "C" {
+        fn AXUIElementCreateSystemWide() -> CFTypeRef;
+        fn AXUIElementCopyAttributeValue(element: CFTypeRef, attribute: CFTypeRef, value: *mut CFTypeRef) -> i32;
+        fn AXUIElementGetPid(element: CFTypeRef, pid: *mut i32) -> i32;
+    }
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        fn CFStringCreateWithBytes(
+            allocator: CFTypeRef,
+            bytes: *const u8,
+            length: isize,
+            encoding: u32,
+            external: bool,
+        ) -> CFTypeRef;
+        fn CFStringGetLength(string: CFTypeRef) -> isize;
+        fn CFStringGetCString(string: CFTypeRef, buffer: *mut c_char, size: isize, encoding: u32) -> bool;
+        fn CFStringGetTypeID() -> usize;
+        fn CFGetTypeID(cf: CFTypeRef) -> usize;
+        fn CFRelease(cf: CFTypeRef);
+    }
+
+    /// An owned CoreFoundation object, released on drop
+    struct Owned(CFTypeRef);
+
+    impl Drop for Owned {
+        fn drop(&mut self) {
+            unsafe { CFRelease(self.0) };
+        }
+    }
+
+    fn cf_string(s: &str) -> Owned {
+        unsafe {
+            Owned(CFStringCreateWithBytes(
+                std::ptr::null(),
+                s.as_ptr(),
+                s.len() as isize,
+                K_CF_STRING_ENCODING_UTF8,
+                false,
+            ))
+        }
+    }
+
+    fn to_string(value: &Owned) -> Option<String> {
+        unsafe {
+            if CFGetTypeID(value.0) != CFStringGetTypeID() {
+                return None;
+            }
+            let size = CFStringGetLength(value.0) * 4 + 1;
+            let mut buffer = vec![0 as c_char; size as usize];
+            if !CFStringGetCString(value.0, buffer.as_mut_ptr(), size, K_CF_STRING_ENCODING_UTF8) {
+                return None;
+            }
+            Some(std::ffi::CStr::from_ptr(buffer.as_ptr()).to_string_lossy().into_owned())
+        }
+    }
+
+    fn attribute(element: &Owned, name: &str) -> Result<Option<Owned>, String> {
+        let name = cf_string(name);
+        let mut value: CFTypeRef = std::ptr::null();
+        match unsafe { AXUIElementCopyAttributeValue(element.0, name.0, &mut value) } {
+            K_AX_ERROR_SUCCESS if !value.is_null() => Ok(Some(Owned(value))),
+            K_AX_ERROR_API_DISABLED => Err("Accessibility permission is required".to_string()),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn focused_window() -> Result<FocusInfo, Box<dyn std::error::Error>> {
+        let system = Owned(unsafe { AXUIElementCreateSystemWide() });
+        let app = attribute(&system, "AXFocusedApplication")?.ok_or("No focused application")?;
+
+        let mut pid = 0;
+        let pid = (unsafe { AXUIElementGetPid(app.0, &mut pid) } == K_AX_ERROR_SUCCESS).then_some(pid as u32);
+        let title = attribute(&app, "AXFocusedWindow")?
+            .and_then(|window| attribute(&window, "AXTitle").ok().flatten())
+            .and_then(|title| to_string(&title));
+        let password_field = attribute(&app, "AXFocusedUIElement")?
+            .map(|element| {
+                attribute(&element, "AXSubrole")
+                    .ok()
+                    .flatten()
+                    .and_then(|subrole| to_string(&subrole))
+                    .is_some_and(|subrole| subrole == "AXSecureTextField")
+            });
+
+        Ok(FocusInfo {
+            app: attribute(&app, "AXTitle")?.and_then(|name| to_string(&name)),
+            title,
+            pid,
+            password_field,
+            source: "accessibility",
+        })
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub fn focused_window() -> Result<FocusInfo, Box<dyn std::error::Error>> {
+    ax::focused_window()
+}
+
+// ============ Windows: foreground window ============
+
+#[cfg(target_os = "windows")]
+mod win32 {
+    use super::FocusInfo;
+    use std::ffi::c_void;
+
+    type Hwnd = *mut c_void;
+    type Handle = *mut c_void;
+
+    const PROCESS_QUERY_LIMITED_INFORMATION: u32 = 0x1000;
+    const GWL_STYLE: i32 = -16;
+    const ES_PASSWORD: i32 = 0x0020;
+
+    #[repr(C)]
+    struct Rect {
+        left: i32,
+        top: i32,
+        right: i32,
+        bottom: i32,
+    }
+
+    #[repr(C)]
+    struct GuiThreadInfo {
+        cb_size: u32,
+        flags: u32,
+        hwnd_active: Hwnd,
+        hwnd_focus: Hwnd,
+        hwnd_capture: Hwnd,
+        hwnd_menu_owner: Hwnd,
+        hwnd_move_size: Hwnd,
+        hwnd_caret: Hwnd,
+        rc_caret: Rect,
+    }
+
+    #[link(name = "user32")]
+    extern "system" {
+        fn GetForegroundWindow() -> Hwnd;
+        fn GetWindowTextW(hwnd: Hwnd, text: *mut u16, max: i32) -> i32;
+        fn GetWindowTextLengthW(hwnd: Hwnd) -> i32;
+        fn GetWindowThreadProcessId(hwnd: Hwnd, pid: *mut u32) -> u32;
+        fn GetGUIThreadInfo(thread: u32, info: *mut GuiThreadInfo) -> i32;
+        fn GetClassNameW(hwnd: Hwnd, name: *mut u16, max: i32) -> i32;
+        fn GetWindowLongW(hwnd: Hwnd, index: i32) -> i32;
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn OpenProcess(access: u32, inherit: i32, pid: u32) -> Handle;
+        fn QueryFullProcessImageNameW(process: Handle, flags: u32, name: *mut u16, size: *mut u32) -> i32;
+        fn CloseHandle(handle: Handle) -> i32;
+    }
+
+    fn window_title(hwnd: Hwnd) -> Option<String> {
+        unsafe {
+            let len = GetWindowTextLengthW(hwnd);
+            if len <= 0 {
+                return None;
+            }
+            let mut buffer = vec![0u16; len as usize + 1];
+            let len = GetWindowTextW(hwnd, buffer.as_mut_ptr(), buffer.len() as i32);
+            Some(String::from_utf16_lossy(&buffer[..len.max(0) as usize]))
+        }
+    }
+
+    /// Executable name (e.g. "notepad.exe") of a process
+    fn process_name(pid: u32) -> Option<String> {
+        unsafe {
+            let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+            if process.is_null() {
+                return None;
+            }
+            let mut buffer = vec![0u16; 1024];
+            let mut size = buffer.len() as u32;
+            let ok = QueryFullProcessImageNameW(process, 0, buffer.as_mut_ptr(), &mut size);
+            CloseHandle(process);
+            if ok == 0 {
+                return None;
+            }
+            let path = String::from_utf16_lossy(&buffer[..size as usize]);
+            path.rsplit('\\').next().map(|name| name.to_string())
+        }
+    }
+
+    /// Only standard Edit controls expose ES_PASSWORD; for anything else
+    /// (browsers, custom UI) it can't be told
+    fn is_password_field(thread: u32) -> Option<bool> {
+        unsafe {
+            let mut info: GuiThreadInfo = std::mem::zeroed();
+            info.cb_size = std::mem::size_of::<GuiThreadInfo>() as u32;
+            if GetGUIThreadInfo(thread, &mut info) == 0 || info.hwnd_focus.is_null() {
+                return None;
+            }
+            let mut class = [0u16; 64];
+            let len = GetClassNameW(info.hwnd_focus, class.as_mut_ptr(), class.len() as i32);
+            let class = String::from_utf16_lossy(&class[..len.max(0) as usize]);
+            class
+                .eq_ignore_ascii_case("Edit")
+                .then(|| GetWindowLongW(info.hwnd_focus, GWL_STYLE) & ES_PASSWORD != 0)
+        }
+    }
+
+    pub fn focused_window() -> Result<FocusInfo, Box<dyn std::error::Error>> {
+        let hwnd = unsafe { GetForegroundWindow() };
+        if hwnd.is_null() {
+            return Err("No focused window".into());
+        }
+        let mut pid = 0;
+        let thread = unsafe { GetWindowThreadProcessId(hwnd, &mut pid) };
+
+        Ok(FocusInfo {
+            app: process_name(pid),
+            title: window_title(hwnd),
+            pid: (pid != 0).then_some(pid),
+            password_field: is_password_field(thread),
+            source: "win32",
+        })
+    }
+}
+
+#[cfg(target_os = "windows")]
+pub fn focused_window() -> Result<FocusInfo, Box<dyn std::error::Error>> {
+    win32::focused_window()
+}
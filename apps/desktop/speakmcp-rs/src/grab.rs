@@ -0,0 +1,348 @@
+//! Exclusive-grab hotkey suppression on Linux
+//! Grabs keyboard devices with EVIOCGRAB so their events only reach us, then
+//! re-injects everything except the configured hotkey combos through a uinput
+//! clone of the device. Without this, holding the dictation hotkey also types
+//! into the focused app.
+//!
+//! The clone copies the device's keys, pointer axes, MSC codes and LEDs, so
+//! keyboards with a built-in pointer keep working, and LED changes the
+//! compositor makes on the clone (Caps Lock etc.) are passed back to the
+//! physical keyboard. evdev's VirtualDeviceBuilder can't declare LEDs, so the
+//! clone is set up with the uinput ioctls directly.
+
+use evdev::{Device, EventType, InputEvent, Synchronization};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::fd::{AsRawFd, BorrowedFd, RawFd};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Name of the uinput devices we create, so the listener never opens them
+pub const VIRTUAL_DEVICE_NAME: &str = "speakmcp-rs virtual keyboard";
+
+/// EVIOCGRAB, _IOW('E', 0x90, int)
+const EVIOCGRAB: u32 = 0x4004_4590;
+
+// uinput ioctls, from linux/uinput.h
+const UI_DEV_CREATE: u32 = 0x5501;
+const UI_DEV_DESTROY: u32 = 0x5502;
+const UI_DEV_SETUP: u32 = 0x405c_5503;
+const UI_ABS_SETUP: u32 = 0x401c_5504;
+const UI_SET_EVBIT: u32 = 0x4004_5564;
+const UI_SET_KEYBIT: u32 = 0x4004_5565;
+const UI_SET_RELBIT: u32 = 0x4004_5566;
+const UI_SET_ABSBIT: u32 = 0x4004_5567;
+const UI_SET_MSCBIT: u32 = 0x4004_5568;
+const UI_SET_LEDBIT: u32 = 0x4004_5569;
+
+/// How often the LED thread checks whether its device was released
+const LED_POLL_INTERVAL_MS: i32 = 500;
+
+/// File descriptors of the devices currently grabbed, so they can be
+/// released from the shutdown handler while their listeners are blocked
+static GRABBED_FDS: Mutex<Vec<RawFd>> = Mutex::new(Vec::new());
+
+/// Hotkey combos to swallow, as rdev-style key names; None disables grabbing
+static GRAB_COMBOS: RwLock<Option<Vec<Vec<String>>>> = RwLock::new(None);
+
+/// Parse combos like "ControlLeft+KeyM" into their key names
+pub fn parse_combos(combos: &[String]) -> Result<Vec<Vec<String>>, String> {
+    combos
+        .iter()
+        .map(|combo| {
+            let keys: Vec<String> = combo
+                .split('+')
+                .map(|key| crate::normalize_key_name(key.trim()))
+                .collect();
+            if keys.iter().any(|key| key.is_empty()) {
+                return Err(format!("Invalid grab combo '{}'", combo));
+            }
+            Ok(keys)
+        })
+        .collect()
+}
+
+/// Set the combos to swallow on devices grabbed from now on
+pub fn configure(combos: Option<Vec<Vec<String>>>) {
+    *GRAB_COMBOS.write().unwrap() = combos;
+}
+
+pub fn enabled() -> bool {
+    GRAB_COMBOS.read().unwrap().is_some()
+}
+
+fn ioctl<T>(fd: RawFd, request: u32, arg: T) -> std::io::Result<()> {
+    if unsafe { libc::ioctl(fd, request as _, arg) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn raw_event(event_type: EventType, code: u16, value: i32) -> libc::input_event {
+    libc::input_event {
+        // The kernel stamps uinput events itself
+        time: libc::timeval { tv_sec: 0, tv_usec: 0 },
+        type_: event_type.0,
+        code,
+        value,
+    }
+}
+
+fn write_events(file: &mut File, events: &[libc::input_event]) -> std::io::Result<()> {
+    let bytes = unsafe {
+        std::slice::from_raw_parts(events.as_ptr() as *const u8, std::mem::size_of_val(events))
+    };
+    file.write_all(bytes)
+}
+
+/// Create a uinput device with the same capabilities as `device`
+fn create_clone(device: &Device) -> std::io::Result<File> {
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/uinput")
+        .map_err(|e| std::io::Error::new(e.kind(), format!("Cannot open /dev/uinput: {}", e)))?;
+    let fd = file.as_raw_fd();
+    let set_bit = |request, bit: u16| ioctl(fd, request, bit as libc::c_int);
+
+    set_bit(UI_SET_EVBIT, EventType::KEY.0)?;
+    for key in device.supported_keys().iter().flat_map(|keys| keys.iter()) {
+        set_bit(UI_SET_KEYBIT, key.code())?;
+    }
+    if let Some(axes) = device.supported_relative_axes() {
+        set_bit(UI_SET_EVBIT, EventType::RELATIVE.0)?;
+        for axis in axes.iter() {
+            set_bit(UI_SET_RELBIT, axis.0)?;
+        }
+    }
+    if let Some(axes) = device.supported_absolute_axes() {
+        set_bit(UI_SET_EVBIT, EventType::ABSOLUTE.0)?;
+        let state = device.get_abs_state()?;
+        for axis in axes.iter() {
+            set_bit(UI_SET_ABSBIT, axis.0)?;
+            let setup = libc::uinput_abs_setup { code: axis.0, absinfo: state[axis.0 as usize] };
+            ioctl(fd, UI_ABS_SETUP, &setup)?;
+        }
+    }
+    if let Some(codes) = device.misc_properties() {
+        set_bit(UI_SET_EVBIT, EventType::MISC.0)?;
+        for code in codes.iter() {
+            set_bit(UI_SET_MSCBIT, code.0)?;
+        }
+    }
+    if let Some(leds) = device.supported_leds() {
+        set_bit(UI_SET_EVBIT, EventType::LED.0)?;
+        for led in leds.iter() {
+            set_bit(UI_SET_LEDBIT, led.0)?;
+        }
+    }
+
+    let id = device.input_id();
+    let mut setup: libc::uinput_setup = unsafe { std::mem::zeroed() };
+    setup.id = libc::input_id {
+        bustype: id.bus_type().0,
+        vendor: id.vendor(),
+        product: id.product(),
+        version: id.version(),
+    };
+    for (dst, src) in setup.name.iter_mut().zip(VIRTUAL_DEVICE_NAME.bytes()) {
+        *dst = src as libc::c_char;
+    }
+    ioctl(fd, UI_DEV_SETUP, &setup)?;
+    ioctl(fd, UI_DEV_CREATE, 0)?;
+    Ok(file)
+}
+
+/// Pass LED changes made on the clone back to the physical keyboard
+/// Runs until `released` is set; owns duplicates of both fds so they can't
+/// be closed (and their numbers reused) under it.
+fn forward_leds(mut clone: File, mut physical: File, released: Arc<AtomicBool>) {
+    let size = std::mem::size_of::<libc::input_event>();
+    let mut buffer = vec![0u8; size * 16];
+
+    while !released.load(Ordering::SeqCst) {
+        let mut poll = libc::pollfd { fd: clone.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+        if unsafe { libc::poll(&mut poll, 1, LED_POLL_INTERVAL_MS) } <= 0 || poll.revents & libc::POLLIN == 0 {
+            continue;
+        }
+        let Ok(read) = clone.read(&mut buffer) else {
+            return;
+        };
+
+        let leds: Vec<libc::input_event> = buffer[..read - read % size]
+            .chunks_exact(size)
+            .map(|bytes| unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const libc::input_event) })
+            .filter(|event| event.type_ == EventType::LED.0)
+            .map(|event| raw_event(EventType::LED, event.code, event.value))
+            .collect();
+        if leds.is_empty() {
+            continue;
+        }
+        let mut frame = leds;
+        frame.push(raw_event(EventType::SYNCHRONIZATION, Synchronization::SYN_REPORT.0, 0));
+        if let Err(e) = write_events(&mut physical, &frame) {
+            eprintln!("Failed to update keyboard LEDs: {}", e);
+        }
+    }
+}
+
+/// A grabbed device and the uinput clone its events are re-injected through
+pub struct GrabbedDevice {
+    /// The grabbed device's fd, registered in GRABBED_FDS while this lives
+    fd: RawFd,
+    clone: File,
+    /// Tells the LED thread to stop
+    released: Arc<AtomicBool>,
+    /// Keys currently held on this device
+    held: HashSet<String>,
+    /// Keys whose press was swallowed, so their repeats and release are too
+    swallowed: HashSet<String>,
+    /// Events of the current frame waiting for SYN_REPORT
+    pending: Vec<InputEvent>,
+}
+
+/// Grab `device` exclusively, after creating the uinput clone so the
+/// keyboard is never left grabbed without a way to re-inject its events
+pub fn grab_device(device: &mut Device) -> std::io::Result<GrabbedDevice> {
+    let clone = create_clone(device)?;
+
+    device.grab()?;
+    let fd = device.as_raw_fd();
+    GRABBED_FDS.lock().unwrap().push(fd);
+
+    // LED writes only reach a grabbed device through the grabbing fd, and a
+    // dup shares it
+    let released = Arc::new(AtomicBool::new(false));
+    if device.supported_leds().is_some() {
+        let fds = unsafe { BorrowedFd::borrow_raw(fd) }
+            .try_clone_to_owned()
+            .and_then(|physical| Ok((clone.try_clone()?, File::from(physical))));
+        match fds {
+            Ok((clone, physical)) => {
+                let released = released.clone();
+                std::thread::spawn(move || forward_leds(clone, physical, released));
+            }
+            Err(e) => eprintln!("Failed to forward LEDs to {}: {}", device.name().unwrap_or("Unknown"), e),
+        }
+    }
+
+    Ok(GrabbedDevice {
+        fd,
+        clone,
+        released,
+        held: HashSet::new(),
+        swallowed: HashSet::new(),
+        pending: Vec::new(),
+    })
+}
+
+/// Release every grabbed keyboard so it goes straight back to other apps
+/// Safe to call from any thread; used on shutdown
+pub fn release_all() {
+    for fd in GRABBED_FDS.lock().unwrap().drain(..) {
+        unsafe {
+            libc::ioctl(fd, EVIOCGRAB as _, 0);
+        }
+    }
+}
+
+impl Drop for GrabbedDevice {
+    // Runs before the listener drops (and closes) the device itself
+    fn drop(&mut self) {
+        GRABBED_FDS.lock().unwrap().retain(|fd| *fd != self.fd);
+        // The LED thread holds its own fd to the clone, so closing ours
+        // wouldn't remove the device
+        self.released.store(true, Ordering::SeqCst);
+        let _ = ioctl(self.clone.as_raw_fd(), UI_DEV_DESTROY, 0);
+    }
+}
+
+impl GrabbedDevice {
+    /// Whether a key press completes one of the configured combos
+    fn completes_combo(&self, key_name: &str) -> bool {
+        let combos = GRAB_COMBOS.read().unwrap();
+        combos.iter().flatten().any(|combo| {
+            let (last, rest) = combo.split_last().unwrap();
+            last == key_name && rest.iter().all(|key| self.held.contains(key))
+        })
+    }
+
+    /// Whether a key event should be passed on to other apps
+    fn should_forward(&mut self, key_name: &str, value: i32) -> bool {
+        match value {
+            // Press
+            1 => {
+                self.held.insert(key_name.to_string());
+                // Hotkeys are only swallowed while someone is listening for them
+                if crate::EMIT_INPUT_EVENTS.load(Ordering::SeqCst) && self.completes_combo(key_name) {
+                    self.swallowed.insert(key_name.to_string());
+                    return false;
+                }
+                true
+            }
+            // Release
+            0 => {
+                self.held.remove(key_name);
+                !self.swallowed.remove(key_name)
+            }
+            // Auto-repeat
+            _ => !self.swallowed.contains(key_name),
+        }
+    }
+
+    /// Re-inject an event unless it belongs to a swallowed hotkey
+    /// `key_name` is the rdev-style name for key events, None otherwise
+    pub fn handle(&mut self, event: &InputEvent, key_name: Option<&str>) -> std::io::Result<()> {
+        if event.event_type() == EventType::SYNCHRONIZATION {
+            if event.code() == Synchronization::SYN_REPORT.0 && !self.pending.is_empty() {
+                let mut frame: Vec<libc::input_event> = self.pending.iter().map(|event| *event.as_ref()).collect();
+                frame.push(raw_event(EventType::SYNCHRONIZATION, Synchronization::SYN_REPORT.0, 0));
+                write_events(&mut self.clone, &frame)?;
+                self.pending.clear();
+            }
+            return Ok(());
+        }
+        // LED state flows the other way, from the clone to the device
+        if event.event_type() == EventType::LED {
+            return Ok(());
+        }
+
+        if let Some(key_name) = key_name {
+            if !self.should_forward(key_name, event.value()) {
+                return Ok(());
+            }
+        }
+        self.pending.push(*event);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn combos(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_combos_into_key_names() {
+        let parsed = parse_combos(&combos(&["ControlLeft+KeyM", " ShiftLeft + Fn ", "F13"])).unwrap();
+        assert_eq!(
+            parsed,
+            [vec!["ControlLeft", "KeyM"], vec!["ShiftLeft", "Function"], vec!["F13"]]
+        );
+        assert!(parse_combos(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_combos_with_empty_keys() {
+        for combo in ["", "ControlLeft+", "+KeyM", "ControlLeft++KeyM", " + "] {
+            assert_eq!(
+                parse_combos(&combos(&["KeyA", combo])),
+                Err(format!("Invalid grab combo '{}'", combo)),
+            );
+        }
+    }
+}
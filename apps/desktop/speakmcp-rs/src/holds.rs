@@ -0,0 +1,295 @@
+//! Native hold and chord detection
+//! Synthesizes HoldStart/HoldEnd and Chord events from the raw key stream so
+//! the host doesn't have to reconstruct push-to-talk timing from
+//! KeyPress/KeyRelease pairs. Raw key events are still emitted alongside.
+//!
+//! A hold starts once the set of pressed keys has stayed unchanged for the
+//! hold delay. Pressing another key before that cancels it (so Ctrl+C never
+//! turns into a Ctrl hold), and pressing one during a hold ends it.
+
+use crate::{output_event, KeyboardEvent};
+use serde_json::json;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex, Once};
+use std::time::{Duration, Instant};
+
+/// Default delay before a held key becomes a hold, matching the desktop
+/// app's HOLD_TO_RECORD_DELAY_MS
+pub const DEFAULT_HOLD_DELAY_MS: u64 = 250;
+
+/// Hold delay in milliseconds; 0 disables hold and chord detection
+static HOLD_DELAY_MS: AtomicU64 = AtomicU64::new(0);
+
+static STATE: Mutex<HoldState> = Mutex::new(HoldState::new());
+static WAKE: Condvar = Condvar::new();
+static TIMER: Once = Once::new();
+
+struct HoldState {
+    /// Currently pressed keys in press order
+    pressed: Vec<String>,
+    /// When the current key set becomes a hold, unless it changes first
+    deadline: Option<Instant>,
+    /// The hold in progress: its keys and when the set was completed
+    active: Option<(Vec<String>, Instant)>,
+}
+
+/// A synthesized event: its type, name and data
+type Synthesized = (&'static str, String, serde_json::Value);
+
+impl HoldState {
+    const fn new() -> Self {
+        HoldState { pressed: Vec::new(), deadline: None, active: None }
+    }
+
+    /// Forget all pressed keys and any pending or active hold
+    fn reset(&mut self) {
+        *self = HoldState::new();
+    }
+
+    fn end_hold(&mut self, now: Instant, interrupted: bool) -> Option<Synthesized> {
+        let (keys, started) = self.active.take()?;
+        let duration_ms = now.duration_since(started).as_millis() as u64;
+        Some((
+            "HoldEnd",
+            keys[0].clone(),
+            json!({"key": keys[0], "keys": keys, "duration_ms": duration_ms, "interrupted": interrupted}),
+        ))
+    }
+
+    fn press(&mut self, key: &str, now: Instant, delay: Duration) -> Vec<Synthesized> {
+        // Auto-repeat presses of a held key don't change anything
+        if self.pressed.iter().any(|k| k == key) {
+            return Vec::new();
+        }
+
+        let mut events: Vec<Synthesized> = self.end_hold(now, true).into_iter().collect();
+        self.pressed.push(key.to_string());
+        if self.pressed.len() > 1 {
+            events.push(("Chord", self.pressed.join("+"), json!({"keys": self.pressed})));
+        }
+
+        self.deadline = Some(now + delay);
+        events
+    }
+
+    fn release(&mut self, key: &str, now: Instant) -> Option<Synthesized> {
+        let index = self.pressed.iter().position(|k| k == key)?;
+        self.pressed.remove(index);
+
+        // Releasing any key cancels a pending hold; the remaining keys only
+        // become a hold again after a new press
+        self.deadline = None;
+        self.end_hold(now, false)
+    }
+
+    /// Start the hold once its deadline has passed
+    fn expire(&mut self, now: Instant, delay: Duration) -> Option<Synthesized> {
+        let deadline = self.deadline.filter(|deadline| now >= *deadline)?;
+        self.deadline = None;
+        if self.pressed.is_empty() {
+            return None;
+        }
+
+        let keys = self.pressed.clone();
+        // Durations count from when the key set was completed
+        self.active = Some((keys.clone(), deadline - delay));
+        Some(("HoldStart", keys[0].clone(), json!({"key": keys[0], "keys": keys})))
+    }
+}
+
+fn output_synthesized((event_type, name, data): Synthesized) {
+    output_event(&KeyboardEvent {
+        event_type: event_type.to_string(),
+        name: Some(name),
+        time: std::time::SystemTime::now(),
+        elapsed_ms: crate::elapsed_ms(),
+        data: data.to_string(),
+    });
+}
+
+/// Enable hold/chord detection with the given delay, or disable it with None
+pub fn configure(hold_delay_ms: Option<u64>) {
+    let mut state = STATE.lock().unwrap();
+    // Keys held while the config is reloaded stay tracked; only disabling forgets them
+    if hold_delay_ms.is_none() {
+        *state = HoldState::new();
+    }
+    HOLD_DELAY_MS.store(hold_delay_ms.unwrap_or(0), Ordering::SeqCst);
+
+    if hold_delay_ms.is_some() {
+        TIMER.call_once(|| {
+            std::thread::spawn(run_timer);
+        });
+    }
+    WAKE.notify_all();
+}
+
+/// Forget tracked keys when events are muted or unmuted: releases made while
+/// muted never reach key_release, so the pressed set would go stale
+pub fn reset() {
+    STATE.lock().unwrap().reset();
+    WAKE.notify_all();
+}
+
+pub fn key_press(key: &str) {
+    let delay_ms = HOLD_DELAY_MS.load(Ordering::SeqCst);
+    if delay_ms == 0 {
+        return;
+    }
+
+    let mut state = STATE.lock().unwrap();
+    for event in state.press(key, Instant::now(), Duration::from_millis(delay_ms)) {
+        output_synthesized(event);
+    }
+    WAKE.notify_all();
+}
+
+pub fn key_release(key: &str) {
+    if HOLD_DELAY_MS.load(Ordering::SeqCst) == 0 {
+        return;
+    }
+
+    let mut state = STATE.lock().unwrap();
+    if let Some(event) = state.release(key, Instant::now()) {
+        output_synthesized(event);
+    }
+}
+
+fn run_timer() {
+    let mut state = STATE.lock().unwrap();
+    loop {
+        state = match state.deadline {
+            None => WAKE.wait(state).unwrap(),
+            Some(deadline) => {
+                let now = Instant::now();
+                if now < deadline {
+                    WAKE.wait_timeout(state, deadline - now).unwrap().0
+                } else {
+                    let delay = Duration::from_millis(HOLD_DELAY_MS.load(Ordering::SeqCst));
+                    if let Some(event) = state.expire(now, delay) {
+                        // A deadline armed just before listen_stop must not fire while muted
+                        if crate::EMIT_INPUT_EVENTS.load(Ordering::SeqCst) {
+                            output_synthesized(event);
+                        }
+                    }
+                    state
+                }
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DELAY: Duration = Duration::from_millis(250);
+
+    fn ms(start: Instant, ms: u64) -> Instant {
+        start + Duration::from_millis(ms)
+    }
+
+    fn types(events: &[Synthesized]) -> Vec<&'static str> {
+        events.iter().map(|(event_type, _, _)| *event_type).collect()
+    }
+
+    #[test]
+    fn held_key_becomes_a_hold() {
+        let start = Instant::now();
+        let mut state = HoldState::new();
+
+        assert!(state.press("ControlLeft", start, DELAY).is_empty());
+        assert!(state.expire(ms(start, 100), DELAY).is_none());
+
+        let (event_type, name, data) = state.expire(ms(start, 250), DELAY).unwrap();
+        assert_eq!((event_type, name.as_str()), ("HoldStart", "ControlLeft"));
+        assert_eq!(data["keys"], json!(["ControlLeft"]));
+
+        let (event_type, _, data) = state.release("ControlLeft", ms(start, 1000)).unwrap();
+        assert_eq!(event_type, "HoldEnd");
+        assert_eq!(data["duration_ms"], 1000);
+        assert_eq!(data["interrupted"], false);
+    }
+
+    #[test]
+    fn ctrl_c_is_a_chord_not_a_ctrl_hold() {
+        let start = Instant::now();
+        let mut state = HoldState::new();
+
+        state.press("ControlLeft", start, DELAY);
+        let events = state.press("KeyC", ms(start, 50), DELAY);
+        assert_eq!(types(&events), ["Chord"]);
+        assert_eq!(events[0].1, "ControlLeft+KeyC");
+
+        // Ctrl's own deadline has passed, but the key set changed since
+        assert!(state.expire(ms(start, 260), DELAY).is_none());
+        assert!(state.release("KeyC", ms(start, 100)).is_none());
+        assert!(state.expire(ms(start, 400), DELAY).is_none());
+        assert!(state.release("ControlLeft", ms(start, 500)).is_none());
+    }
+
+    #[test]
+    fn pressing_a_key_during_a_hold_interrupts_it() {
+        let start = Instant::now();
+        let mut state = HoldState::new();
+
+        state.press("AltLeft", start, DELAY);
+        state.expire(ms(start, 250), DELAY).unwrap();
+
+        let events = state.press("KeyA", ms(start, 600), DELAY);
+        assert_eq!(types(&events), ["HoldEnd", "Chord"]);
+        assert_eq!(events[0].2["interrupted"], true);
+        assert_eq!(events[0].2["duration_ms"], 600);
+
+        // The new key set can become a hold of its own
+        let (_, name, data) = state.expire(ms(start, 850), DELAY).unwrap();
+        assert_eq!(name, "AltLeft");
+        assert_eq!(data["keys"], json!(["AltLeft", "KeyA"]));
+    }
+
+    #[test]
+    fn auto_repeat_is_ignored() {
+        let start = Instant::now();
+        let mut state = HoldState::new();
+
+        state.press("Space", start, DELAY);
+        // A repeat must not push the deadline back
+        assert!(state.press("Space", ms(start, 200), DELAY).is_empty());
+        assert!(state.expire(ms(start, 250), DELAY).is_some());
+        assert!(state.press("Space", ms(start, 300), DELAY).is_empty());
+        assert!(state.active.is_some());
+    }
+
+    #[test]
+    fn releasing_a_key_waits_for_a_new_press() {
+        let start = Instant::now();
+        let mut state = HoldState::new();
+
+        state.press("ShiftLeft", start, DELAY);
+        state.press("KeyA", ms(start, 10), DELAY);
+        state.release("KeyA", ms(start, 20));
+        // Shift alone is still down, but only a new press starts a hold
+        assert!(state.expire(ms(start, 1000), DELAY).is_none());
+    }
+
+    #[test]
+    fn muting_forgets_keys_released_while_muted() {
+        let start = Instant::now();
+        let mut state = HoldState::new();
+
+        state.press("ControlLeft", start, DELAY);
+        // listen_stop; the release while muted is never reported
+        state.reset();
+        // listen_start
+        state.reset();
+
+        // The first press after unmuting is a new press, not auto-repeat
+        assert!(state.press("ControlLeft", ms(start, 1000), DELAY).is_empty());
+        let (event_type, name, _) = state.expire(ms(start, 1250), DELAY).unwrap();
+        assert_eq!((event_type, name.as_str()), ("HoldStart", "ControlLeft"));
+        state.release("ControlLeft", ms(start, 1500));
+
+        // and a different key is not chorded with the stale one
+        assert!(state.press("KeyA", ms(start, 2000), DELAY).is_empty());
+    }
+}
@@ -0,0 +1,207 @@
+//! Single-instance lock for the keyboard listener
+//! If the desktop app restarts while an old listener is still alive, both emit
+//! every key event. Only running listeners take the lock, so a `serve` process
+//! used just for write/press can run alongside one. The lock is an OS file lock, so it is
+//! released when the holder exits or crashes and never goes stale. The holder
+//! writes its PID into the locked file once it has the lock, so `--takeover`
+//! only ever reads the PID of the process holding it.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// How long `--takeover` waits for the old instance to exit
+const TAKEOVER_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// The held lock file, kept open for the life of the process
+static LOCK: OnceLock<File> = OnceLock::new();
+
+/// Per-user lock file path
+fn lock_path() -> PathBuf {
+    #[cfg(target_os = "linux")]
+    let (dir, name) = match std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        Some(dir) => (PathBuf::from(dir), "speakmcp-rs".to_string()),
+        // /tmp is shared by every user, so the name carries the user id
+        None => (std::env::temp_dir(), format!("speakmcp-rs-{}", unsafe { libc::getuid() })),
+    };
+    // The temp dir is already per-user on macOS and Windows
+    #[cfg(not(target_os = "linux"))]
+    let (dir, name) = (std::env::temp_dir(), "speakmcp-rs".to_string());
+
+    dir.join(format!("{}.lock", name))
+}
+
+/// Options for opening the lock file without following a symlink someone
+/// else planted in its place
+fn open_options() -> OpenOptions {
+    let mut options = OpenOptions::new();
+    options.read(true).write(true).create(true).truncate(false);
+    #[cfg(target_os = "linux")]
+    std::os::unix::fs::OpenOptionsExt::custom_flags(&mut options, libc::O_NOFOLLOW);
+    options
+}
+
+/// flock() is advisory, so the PID stays readable by the processes locked out
+#[cfg(unix)]
+fn try_lock(file: &File) -> Result<bool, String> {
+    match file.try_lock() {
+        Ok(()) => Ok(true),
+        Err(std::fs::TryLockError::WouldBlock) => Ok(false),
+        Err(std::fs::TryLockError::Error(e)) => Err(format!("Failed to lock instance file: {}", e)),
+    }
+}
+
+/// Windows locks are mandatory and File::try_lock locks the whole file, which
+/// would make the PID unreadable; lock a single byte far past it instead
+#[cfg(windows)]
+fn try_lock(file: &File) -> Result<bool, String> {
+    use std::os::windows::io::AsRawHandle;
+    use win32::*;
+
+    let mut overlapped = Overlapped {
+        internal: 0,
+        internal_high: 0,
+        offset: 0,
+        offset_high: LOCK_OFFSET_HIGH,
+        event: std::ptr::null_mut(),
+    };
+    let flags = LOCKFILE_EXCLUSIVE_LOCK | LOCKFILE_FAIL_IMMEDIATELY;
+    if unsafe { LockFileEx(file.as_raw_handle(), flags, 0, 1, 0, &mut overlapped) } != 0 {
+        return Ok(true);
+    }
+    let error = std::io::Error::last_os_error();
+    match error.raw_os_error() {
+        Some(ERROR_LOCK_VIOLATION) => Ok(false),
+        _ => Err(format!("Failed to lock instance file: {}", error)),
+    }
+}
+
+#[cfg(windows)]
+mod win32 {
+    pub type Handle = *mut std::ffi::c_void;
+    pub const LOCKFILE_FAIL_IMMEDIATELY: u32 = 0x1;
+    pub const LOCKFILE_EXCLUSIVE_LOCK: u32 = 0x2;
+    pub const ERROR_LOCK_VIOLATION: i32 = 33;
+    pub const PROCESS_TERMINATE: u32 = 0x0001;
+    /// The locked byte is at 2^62
+    pub const LOCK_OFFSET_HIGH: u32 = 1 << 30;
+
+    #[repr(C)]
+    pub struct Overlapped {
+        pub internal: usize,
+        pub internal_high: usize,
+        pub offset: u32,
+        pub offset_high: u32,
+        pub event: Handle,
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        pub fn LockFileEx(
+            file: Handle,
+            flags: u32,
+            reserved: u32,
+            bytes_low: u32,
+            bytes_high: u32,
+            overlapped: *mut Overlapped,
+        ) -> i32;
+        pub fn OpenProcess(access: u32, inherit: i32, pid: u32) -> Handle;
+        pub fn TerminateProcess(process: Handle, exit_code: u32) -> i32;
+        pub fn CloseHandle(handle: Handle) -> i32;
+    }
+}
+
+/// The PID written by the lock holder, if it has finished writing it
+/// The newline is written last, so a half-written PID doesn't parse
+fn read_pid(path: &PathBuf) -> Option<u32> {
+    let contents = std::fs::read_to_string(path).ok()?;
+    contents.strip_suffix('\n')?.parse().ok()
+}
+
+/// Ask another process to exit; on Unix this is SIGTERM, which it handles
+/// by releasing its grabs and shutting down cleanly
+#[cfg(unix)]
+fn terminate(pid: u32) -> Result<(), String> {
+    if unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) } == 0 {
+        return Ok(());
+    }
+    let error = std::io::Error::last_os_error();
+    match error.raw_os_error() {
+        // Already gone; the lock is about to be released
+        Some(libc::ESRCH) => Ok(()),
+        _ => Err(format!("Failed to stop the running instance (pid {}): {}", pid, error)),
+    }
+}
+
+#[cfg(windows)]
+fn terminate(pid: u32) -> Result<(), String> {
+    use win32::*;
+
+    unsafe {
+        let process = OpenProcess(PROCESS_TERMINATE, 0, pid);
+        if process.is_null() {
+            let error = std::io::Error::last_os_error();
+            return Err(format!("Failed to stop the running instance (pid {}): {}", pid, error));
+        }
+        let ok = TerminateProcess(process, 1);
+        let error = std::io::Error::last_os_error();
+        CloseHandle(process);
+        if ok == 0 {
+            return Err(format!("Failed to stop the running instance (pid {}): {}", pid, error));
+        }
+    }
+    Ok(())
+}
+
+/// Make this the only running listener; a no-op once the lock is held
+/// With `takeover`, a running instance is stopped first; otherwise it is an error
+pub fn acquire(takeover: bool) -> Result<(), String> {
+    if LOCK.get().is_some() {
+        return Ok(());
+    }
+    let path = lock_path();
+
+    let mut file = open_options()
+        .open(&path)
+        .map_err(|e| format!("Cannot open {}: {}", path.display(), e))?;
+
+    if !try_lock(&file)? {
+        if !takeover {
+            let running = read_pid(&path).map_or("another process".to_string(), |pid| format!("pid {}", pid));
+            return Err(format!(
+                "speakmcp-rs is already running ({}); pass --takeover to replace it",
+                running
+            ));
+        }
+
+        let started = Instant::now();
+        let mut seen = None;
+        let mut stopped = None;
+        while !try_lock(&file)? {
+            if started.elapsed() > TAKEOVER_TIMEOUT {
+                let running = stopped.map_or("pid unknown".to_string(), |pid| format!("pid {}", pid));
+                return Err(format!("Running instance ({}) did not exit", running));
+            }
+            // A holder that has just taken the lock may not have replaced the
+            // previous holder's PID yet, so only stop a PID still there on the
+            // next pass; a missing one means waiting for it to be written
+            let pid = read_pid(&path);
+            if let Some(pid) = pid.filter(|pid| seen == Some(*pid) && stopped != Some(*pid)) {
+                eprintln!("Stopping running instance (pid {})", pid);
+                terminate(pid)?;
+                stopped = Some(pid);
+            }
+            seen = pid;
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    file.set_len(0)
+        .and_then(|()| writeln!(file, "{}", std::process::id()))
+        .map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+
+    let _ = LOCK.set(file);
+    Ok(())
+}
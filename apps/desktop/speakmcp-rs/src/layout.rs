@@ -0,0 +1,232 @@
+//! Layout-aware key naming for the evdev listener
+//! evdev reports physical keys, so on AZERTY the key labelled "A" arrives as
+//! KEY_Q. This resolves keycodes through an XKB keymap for the user's layout
+//! (libxkbcommon is loaded at runtime, so it's optional) so events can carry
+//! both the physical name and the layout-resolved one.
+
+use std::collections::HashMap;
+use std::ffi::{c_char, CStr, CString};
+use std::sync::Mutex;
+use xkbcommon_dl::{
+    xkb_context_flags, xkb_keymap, xkb_keymap_compile_flags, xkb_rule_names, xkbcommon_option,
+};
+
+/// Keycodes in XKB are evdev codes offset by 8
+const XKB_KEYCODE_OFFSET: u32 = 8;
+
+/// A compiled XKB keymap
+pub struct Keymap(*mut xkb_keymap);
+
+// The keymap is immutable once compiled and only used behind the mutex
+unsafe impl Send for Keymap {}
+
+impl Drop for Keymap {
+    fn drop(&mut self) {
+        // A keymap can only have been compiled if libxkbcommon loaded
+        if let Some(xkb) = xkbcommon_option() {
+            unsafe { (xkb.xkb_keymap_unref)(self.0) };
+        }
+    }
+}
+
+static KEYMAP: Mutex<Option<Keymap>> = Mutex::new(None);
+
+/// A key as resolved through the active layout
+pub struct ResolvedKey {
+    /// rdev-style name of the key the layout puts here, e.g. "KeyA" for KEY_Q on AZERTY
+    pub layout_key: Option<String>,
+    /// XKB keysym name of the unshifted symbol, e.g. "a" or "eacute"
+    pub keysym: String,
+    /// Text the unshifted key produces, if any
+    pub char: Option<String>,
+}
+
+/// Read a `KEY=value` setting from a shell-style config file like /etc/default/keyboard
+fn read_shell_setting(contents: &str, key: &str) -> Option<String> {
+    contents.lines().find_map(|line| {
+        let value = line.trim().strip_prefix(key)?.strip_prefix('=')?;
+        let value = value.trim().trim_matches('"').trim_matches('\'');
+        (!value.is_empty()).then(|| value.to_string())
+    })
+}
+
+/// Read an `Option "XkbLayout" "fr"` line from an xorg.conf snippet
+fn read_xorg_option(contents: &str, option: &str) -> Option<String> {
+    let needle = format!("\"{}\"", option);
+    contents.lines().find_map(|line| {
+        let rest = line.trim().strip_prefix("Option")?.trim().strip_prefix(needle.as_str())?;
+        let value = rest.trim().trim_matches('"');
+        (!value.is_empty()).then(|| value.to_string())
+    })
+}
+
+/// Find the system keyboard layout and variant from the usual config files
+fn system_layout() -> Option<(String, Option<String>)> {
+    if let Ok(contents) = std::fs::read_to_string("/etc/default/keyboard") {
+        if let Some(layout) = read_shell_setting(&contents, "XKBLAYOUT") {
+            return Some((layout, read_shell_setting(&contents, "XKBVARIANT")));
+        }
+    }
+    if let Ok(contents) = std::fs::read_to_string("/etc/X11/xorg.conf.d/00-keyboard.conf") {
+        if let Some(layout) = read_xorg_option(&contents, "XkbLayout") {
+            return Some((layout, read_xorg_option(&contents, "XkbVariant")));
+        }
+    }
+    None
+}
+
+/// Compile the keymap for a layout without installing it
+/// `layout` is "layout" or "layout:variant"; without it, XKB_DEFAULT_LAYOUT
+/// and then the system keyboard config are used. Gives None when
+/// libxkbcommon isn't available and no layout was asked for.
+pub fn compile(layout: Option<&str>) -> Result<Option<Keymap>, String> {
+    let Some(xkb) = xkbcommon_option() else {
+        return if layout.is_some() {
+            Err("--layout requires libxkbcommon, which could not be loaded".to_string())
+        } else {
+            Ok(None)
+        };
+    };
+
+    let (layout, variant) = match layout {
+        Some(layout) => match layout.split_once(':') {
+            Some((layout, variant)) => (Some(layout.to_string()), Some(variant.to_string())),
+            None => (Some(layout.to_string()), None),
+        },
+        // Leaving the names empty makes xkbcommon honor XKB_DEFAULT_* itself
+        None if std::env::var_os("XKB_DEFAULT_LAYOUT").is_some() => (None, None),
+        None => match system_layout() {
+            Some((layout, variant)) => (Some(layout), variant),
+            None => (None, None),
+        },
+    };
+
+    let layout = layout.map(|l| CString::new(l).map_err(|_| "Invalid layout name")).transpose()?;
+    let variant = variant.map(|v| CString::new(v).map_err(|_| "Invalid layout variant")).transpose()?;
+    let names = xkb_rule_names {
+        rules: std::ptr::null(),
+        model: std::ptr::null(),
+        layout: layout.as_ref().map_or(std::ptr::null(), |l| l.as_ptr()),
+        variant: variant.as_ref().map_or(std::ptr::null(), |v| v.as_ptr()),
+        options: std::ptr::null(),
+    };
+
+    let keymap = unsafe {
+        let context = (xkb.xkb_context_new)(xkb_context_flags::XKB_CONTEXT_NO_FLAGS);
+        if context.is_null() {
+            return Err("Failed to create xkbcommon context".to_string());
+        }
+        let keymap = (xkb.xkb_keymap_new_from_names)(
+            context,
+            &names,
+            xkb_keymap_compile_flags::XKB_KEYMAP_COMPILE_NO_FLAGS,
+        );
+        // The keymap holds its own reference to the context
+        (xkb.xkb_context_unref)(context);
+        keymap
+    };
+    if keymap.is_null() {
+        return Err("Failed to compile keymap for the keyboard layout".to_string());
+    }
+    Ok(Some(Keymap(keymap)))
+}
+
+/// Make a compiled keymap the one used to resolve key names
+pub fn install(keymap: Option<Keymap>) {
+    *KEYMAP.lock().unwrap() = keymap;
+}
+
+impl Keymap {
+    /// The evdev key, and whether Shift is needed, that types each character
+    /// on this layout. Only the unshifted and Shift levels are used, so
+    /// characters that need AltGr or dead keys are left out.
+    pub fn char_keys(&self) -> HashMap<char, (u16, bool)> {
+        let mut keys = HashMap::new();
+        let Some(xkb) = xkbcommon_option() else {
+            return keys;
+        };
+
+        unsafe {
+            let state = (xkb.xkb_state_new)(self.0);
+            if state.is_null() {
+                return keys;
+            }
+            let shift_index = (xkb.xkb_keymap_mod_get_index)(self.0, c"Shift".as_ptr());
+            let min = (xkb.xkb_keymap_min_keycode)(self.0);
+            let max = (xkb.xkb_keymap_max_keycode)(self.0);
+
+            for shift in [false, true] {
+                let mods = match shift {
+                    false => 0,
+                    true if shift_index < u32::BITS => 1 << shift_index,
+                    // No Shift modifier in this keymap
+                    true => break,
+                };
+                (xkb.xkb_state_update_mask)(state, mods, 0, 0, 0, 0, 0);
+                // Lower keycodes win, so the main block is preferred over the keypad
+                for keycode in min.max(XKB_KEYCODE_OFFSET)..=max {
+                    let c = match char::from_u32((xkb.xkb_state_key_get_utf32)(state, keycode)) {
+                        // Return produces a carriage return
+                        Some('\r') => '\n',
+                        Some(c) if c == '\n' || c == '\t' || !c.is_control() => c,
+                        _ => continue,
+                    };
+                    let code = (keycode - XKB_KEYCODE_OFFSET) as u16;
+                    keys.entry(c).or_insert((code, shift));
+                }
+            }
+            (xkb.xkb_state_unref)(state);
+        }
+        keys
+    }
+}
+
+/// rdev-style name for a layout-resolved character, for letters and digits
+fn rdev_name_for_char(c: &str) -> Option<String> {
+    let mut chars = c.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphabetic() => Some(format!("Key{}", c.to_ascii_uppercase())),
+        (Some(c), None) if c.is_ascii_digit() => Some(format!("Digit{}", c)),
+        _ => None,
+    }
+}
+
+/// Resolve an evdev key code through the configured layout
+pub fn resolve(code: u16) -> Option<ResolvedKey> {
+    let xkb = xkbcommon_option()?;
+    let keymap = KEYMAP.lock().unwrap();
+    let keymap = keymap.as_ref()?.0;
+    let keycode = code as u32 + XKB_KEYCODE_OFFSET;
+
+    // Level 0 of the first layout: the symbol without any modifiers
+    let keysym = unsafe {
+        let mut syms: *const u32 = std::ptr::null();
+        let count = (xkb.xkb_keymap_key_get_syms_by_level)(keymap, keycode, 0, 0, &mut syms);
+        if count < 1 || syms.is_null() {
+            return None;
+        }
+        *syms
+    };
+
+    let mut buffer = [0 as c_char; 64];
+    let keysym_name = unsafe {
+        if (xkb.xkb_keysym_get_name)(keysym, buffer.as_mut_ptr(), buffer.len()) < 0 {
+            return None;
+        }
+        CStr::from_ptr(buffer.as_ptr()).to_string_lossy().into_owned()
+    };
+
+    let char = unsafe {
+        let len = (xkb.xkb_keysym_to_utf8)(keysym, buffer.as_mut_ptr(), buffer.len());
+        // Returns the size including the NUL terminator; control keys give ""
+        (len > 1)
+            .then(|| CStr::from_ptr(buffer.as_ptr()).to_string_lossy().into_owned())
+            .filter(|c| !c.chars().any(char::is_control))
+    };
+
+    Some(ResolvedKey {
+        layout_key: char.as_deref().and_then(rdev_name_for_char),
+        keysym: keysym_name,
+        char,
+    })
+}
@@ -1,5 +1,30 @@
-use serde::Serialize;
+mod clipboard;
+#[cfg(target_os = "linux")]
+mod compose;
+mod crash;
+mod doctor;
+mod eventlog;
+mod focus;
+#[cfg(target_os = "linux")]
+mod grab;
+mod holds;
+mod instance;
+mod output;
+#[cfg(target_os = "macos")]
+mod permissions;
+#[cfg(target_os = "linux")]
+mod layout;
+mod press;
+#[cfg(target_os = "linux")]
+mod uinput;
+#[cfg(target_os = "linux")]
+mod wayland;
+
+use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
 
 // On non-Linux platforms, use rdev
 #[cfg(not(target_os = "linux"))]
@@ -10,9 +35,252 @@ struct KeyboardEvent {
     event_type: String,
     name: Option<String>,
     time: std::time::SystemTime,
+    /// Milliseconds since the epoch reported in the ready handshake, from a
+    /// monotonic clock, so intervals between events survive clock changes
+    elapsed_ms: f64,
     data: String,
 }
 
+/// Reference point for `elapsed_ms`: a monotonic instant and the wall-clock time it was taken
+static EPOCH: std::sync::OnceLock<(std::time::Instant, std::time::SystemTime)> = std::sync::OnceLock::new();
+
+fn epoch() -> &'static (std::time::Instant, std::time::SystemTime) {
+    EPOCH.get_or_init(|| (std::time::Instant::now(), std::time::SystemTime::now()))
+}
+
+/// Monotonic milliseconds since `epoch()`, with microsecond resolution
+fn elapsed_ms() -> f64 {
+    epoch().0.elapsed().as_micros() as f64 / 1000.0
+}
+
+/// Whether input events are forwarded to stdout.
+/// Always on for `listen`; toggled by `listen_start`/`listen_stop` in `serve` mode.
+static EMIT_INPUT_EVENTS: AtomicBool = AtomicBool::new(true);
+
+/// Whether mouse events (ButtonPress/ButtonRelease/MouseMove/Wheel) are emitted.
+/// Off by default since mouse movement is far noisier than key events.
+static EMIT_MOUSE_EVENTS: AtomicBool = AtomicBool::new(false);
+
+/// Keys to emit events for, by rdev-style name; None emits every key.
+/// Set by `listen --keys` so other keystrokes never leave this process.
+static KEY_FILTER: RwLock<Option<HashSet<String>>> = RwLock::new(None);
+
+/// Modifier keys always pass the key filter so the host can track modifier state
+const MODIFIER_KEY_NAMES: &[&str] = &[
+    "ControlLeft", "ControlRight", "ShiftLeft", "ShiftRight",
+    "Alt", "AltRight", "AltGr", "MetaLeft", "MetaRight",
+];
+
+/// Options for `listen`, also accepted by the serve-mode `listen_start` command
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct ListenOptions {
+    /// Also emit mouse events
+    mouse: bool,
+    /// Only emit events for these keys (plus modifiers)
+    keys: Option<Vec<String>>,
+    /// Emit HoldStart/HoldEnd/Chord events, with this hold delay
+    hold_ms: Option<u64>,
+    /// Hotkey combos like "ControlLeft+KeyM" to keep from reaching other apps (Linux only)
+    grab: Option<Vec<String>>,
+    /// Keyboard layout ("fr" or "fr:bepo") used to name keys (Linux only)
+    layout: Option<String>,
+    /// Only listen to input devices matching these names or paths (Linux only)
+    devices: Option<Vec<String>>,
+    /// Never listen to input devices matching these names or paths (Linux only)
+    exclude: Option<Vec<String>>,
+    /// Also append emitted events to this JSONL file
+    log_file: Option<String>,
+    /// Rotate the log file at this many bytes
+    rotate_size: Option<u64>,
+}
+
+/// `listen` arguments, kept so SIGHUP can reload `--config` with the same flags on top
+static LISTEN_ARGS: std::sync::OnceLock<Vec<String>> = std::sync::OnceLock::new();
+
+/// Parse `listen [--config <file>] [--mouse] [--keys <name,name,...>] [--holds] [--hold-ms <n>] [--grab <combo,combo,...>]
+/// [--layout <layout[:variant]>] [--device <name-or-path>]... [--exclude <name-or-path>]... [--log-file <path> [--rotate-size <size>]]`
+/// The `--config` file holds the same options as JSON (as in `listen_start`); flags override it
+fn parse_listen_args(args: &[String]) -> Result<ListenOptions, String> {
+    let config = args.iter().position(|arg| arg == "--config").map(|i| {
+        args.get(i + 1).ok_or_else(|| "--config requires a path".to_string())
+    });
+    let mut options = match config.transpose()? {
+        Some(path) => {
+            let contents = std::fs::read_to_string(path)
+                .map_err(|e| format!("Cannot read config {}: {}", path, e))?;
+            serde_json::from_str(&contents).map_err(|e| format!("Invalid config {}: {}", path, e))?
+        }
+        None => ListenOptions::default(),
+    };
+    // Repeated flags build one list that replaces the config's, like every other flag
+    let mut devices = Vec::new();
+    let mut exclude = Vec::new();
+    let mut iter = args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            // Already loaded above
+            "--config" => {
+                iter.next();
+            }
+            "--mouse" => options.mouse = true,
+            "--keys" => {
+                let value = iter.next().ok_or("--keys requires a comma-separated list of key names")?;
+                let keys: Vec<String> = value
+                    .split(',')
+                    .map(|key| key.trim().to_string())
+                    .filter(|key| !key.is_empty())
+                    .collect();
+                if keys.is_empty() {
+                    return Err("--keys requires at least one key name".to_string());
+                }
+                options.keys = Some(keys);
+            }
+            "--holds" => {
+                options.hold_ms.get_or_insert(holds::DEFAULT_HOLD_DELAY_MS);
+            }
+            "--hold-ms" => {
+                let value = iter.next().ok_or("--hold-ms requires a value")?;
+                let hold_ms = value
+                    .parse()
+                    .ok()
+                    .filter(|ms| *ms > 0)
+                    .ok_or_else(|| format!("Invalid --hold-ms value '{}'", value))?;
+                options.hold_ms = Some(hold_ms);
+            }
+            "--grab" => {
+                let value = iter.next().ok_or("--grab requires a comma-separated list of key combos")?;
+                options.grab = Some(value.split(',').map(|combo| combo.trim().to_string()).collect());
+            }
+            "--layout" => {
+                let value = iter.next().ok_or("--layout requires a value")?;
+                options.layout = Some(value.clone());
+            }
+            "--device" => {
+                let value = iter.next().ok_or("--device requires a device name or path")?;
+                devices.push(value.clone());
+            }
+            "--exclude" => {
+                let value = iter.next().ok_or("--exclude requires a device name or path")?;
+                exclude.push(value.clone());
+            }
+            "--log-file" => {
+                let value = iter.next().ok_or("--log-file requires a path")?;
+                options.log_file = Some(value.clone());
+            }
+            "--rotate-size" => {
+                let value = iter.next().ok_or("--rotate-size requires a size like 10M")?;
+                options.rotate_size = Some(eventlog::parse_size(value)?);
+            }
+            _ => return Err(format!("Unknown listen option '{}'", arg)),
+        }
+    }
+    if !devices.is_empty() {
+        options.devices = Some(devices);
+    }
+    if !exclude.is_empty() {
+        options.exclude = Some(exclude);
+    }
+    Ok(options)
+}
+
+/// Map user-facing key name aliases to the names in the event stream
+fn normalize_key_name(name: &str) -> String {
+    // "Fn" is what users call it; both backends report "Function"
+    if name == "Fn" { "Function".to_string() } else { name.to_string() }
+}
+
+/// Apply listen options, at startup or when reloading a running listener
+/// Everything that can fail is done first so a bad config changes nothing;
+/// held keys and active holds carry over
+fn apply_listen_options(options: &ListenOptions) -> Result<(), String> {
+    #[cfg(not(target_os = "linux"))]
+    if options.grab.is_some() || options.layout.is_some() {
+        return Err("--grab and --layout are only supported on Linux".to_string());
+    }
+    #[cfg(not(target_os = "linux"))]
+    if options.devices.is_some() || options.exclude.is_some() {
+        return Err("--device and --exclude are only supported on Linux".to_string());
+    }
+    #[cfg(target_os = "linux")]
+    let combos = options.grab.as_deref().map(grab::parse_combos).transpose()?;
+    #[cfg(target_os = "linux")]
+    let keymap = layout::compile(options.layout.as_deref())?;
+    let event_log = eventlog::prepare(options.log_file.as_deref(), options.rotate_size)?;
+
+    #[cfg(target_os = "linux")]
+    {
+        layout::install(keymap);
+        // Keyboards are only grabbed when opened, so turning grabbing on
+        // later only affects keyboards connected after that
+        grab::configure(combos);
+        configure_device_filter(options);
+    }
+    eventlog::install(event_log);
+    EMIT_MOUSE_EVENTS.store(options.mouse, Ordering::SeqCst);
+
+    let filter = options.keys.as_ref().map(|keys| {
+        keys.iter().map(|key| normalize_key_name(key)).collect()
+    });
+    *KEY_FILTER.write().unwrap() = filter;
+
+    holds::configure(options.hold_ms);
+
+    // A running listener drops devices that no longer pass the filter (or
+    // pointers, with mouse events off) and opens the ones that now do
+    #[cfg(target_os = "linux")]
+    {
+        DEVICE_FILTER_GENERATION.fetch_add(1, Ordering::SeqCst);
+        if let Some(Err(e)) = LISTENER_DEVICES.get().map(open_new_devices) {
+            eprintln!("!error: {}", e);
+        }
+    }
+    Ok(())
+}
+
+/// Re-read the `listen --config` file on SIGHUP
+fn reload_listen_config() {
+    let Some(args) = LISTEN_ARGS.get().filter(|args| args.iter().any(|arg| arg == "--config")) else {
+        eprintln!("Ignoring SIGHUP: no --config file to reload");
+        return;
+    };
+    match parse_listen_args(args).and_then(|options| apply_listen_options(&options)) {
+        Ok(()) => output_event(&KeyboardEvent {
+            event_type: "ConfigReloaded".to_string(),
+            name: None,
+            time: std::time::SystemTime::now(),
+            elapsed_ms: elapsed_ms(),
+            data: json!({}).to_string(),
+        }),
+        Err(e) => output_error_event("ReloadFailed", &e),
+    }
+}
+
+/// Emit a key event and feed it to hold/chord detection
+fn output_key_event(event: KeyboardEvent, key_name: &str, pressed: bool) {
+    output_event(&event);
+    if pressed {
+        holds::key_press(key_name);
+    } else {
+        holds::key_release(key_name);
+    }
+}
+
+/// Whether events for this key pass the `--keys` filter
+fn key_passes_filter(name: &str) -> bool {
+    match KEY_FILTER.read().unwrap().as_ref() {
+        Some(keys) => keys.contains(name) || MODIFIER_KEY_NAMES.contains(&name),
+        None => true,
+    }
+}
+
+fn output_event(event: &KeyboardEvent) {
+    crash::record_message("out", &event.event_type, event.data.len());
+    eventlog::write(event);
+    output::emit(event);
+}
+
 // ============ Non-Linux (macOS/Windows) implementation using rdev ============
 #[cfg(not(target_os = "linux"))]
 fn deal_event_to_json(event: Event) -> KeyboardEvent {
@@ -20,6 +288,7 @@ fn deal_event_to_json(event: Event) -> KeyboardEvent {
         event_type: "".to_string(),
         name: event.name,
         time: event.time,
+        elapsed_ms: elapsed_ms(),
         data: "".to_string(),
     };
     match event.event_type {
@@ -31,19 +300,47 @@ fn deal_event_to_json(event: Event) -> KeyboardEvent {
             jsonify_event.event_type = "KeyRelease".to_string();
             jsonify_event.data = json!({"key": format!("{:?}", key)}).to_string();
         }
-        _ => {}
+        EventType::ButtonPress(button) => {
+            jsonify_event.event_type = "ButtonPress".to_string();
+            jsonify_event.data = json!({"button": format!("{:?}", button)}).to_string();
+        }
+        EventType::ButtonRelease(button) => {
+            jsonify_event.event_type = "ButtonRelease".to_string();
+            jsonify_event.data = json!({"button": format!("{:?}", button)}).to_string();
+        }
+        EventType::MouseMove { x, y } => {
+            jsonify_event.event_type = "MouseMove".to_string();
+            jsonify_event.data = json!({"x": x, "y": y}).to_string();
+        }
+        EventType::Wheel { delta_x, delta_y } => {
+            jsonify_event.event_type = "Wheel".to_string();
+            jsonify_event.data = json!({"delta_x": delta_x, "delta_y": delta_y}).to_string();
+        }
     }
     jsonify_event
 }
 
 #[cfg(not(target_os = "linux"))]
 fn keyboard_callback(event: Event) {
+    if !EMIT_INPUT_EVENTS.load(Ordering::SeqCst) {
+        return;
+    }
     match event.event_type {
-        EventType::KeyPress(_) | EventType::KeyRelease(_) => {
-            let json_event = deal_event_to_json(event);
-            println!("{}", serde_json::to_string(&json_event).unwrap());
+        EventType::KeyPress(key) | EventType::KeyRelease(key) => {
+            let key_name = format!("{:?}", key);
+            if key_passes_filter(&key_name) {
+                let pressed = matches!(event.event_type, EventType::KeyPress(_));
+                output_key_event(deal_event_to_json(event), &key_name, pressed);
+            }
+        }
+        EventType::ButtonPress(_)
+        | EventType::ButtonRelease(_)
+        | EventType::MouseMove { .. }
+        | EventType::Wheel { .. } => {
+            if EMIT_MOUSE_EVENTS.load(Ordering::SeqCst) {
+                output_event(&deal_event_to_json(event));
+            }
         }
-        _ => {}
     }
 }
 
@@ -181,8 +478,35 @@ fn evdev_key_to_rdev_name(key: evdev::Key) -> String {
         Key::KEY_KPASTERISK => "NumpadMultiply".to_string(),
         Key::KEY_KPSLASH => "NumpadDivide".to_string(),
         Key::KEY_KPDOT => "NumpadDecimal".to_string(),
+        Key::KEY_KPEQUAL => "NumpadEqual".to_string(),
+        Key::KEY_KPCOMMA => "NumpadComma".to_string(),
         Key::KEY_NUMLOCK => "NumLock".to_string(),
 
+        // Media and consumer keys, named after their KeyboardEvent.code
+        // (rdev has no names for these)
+        Key::KEY_VOLUMEUP => "AudioVolumeUp".to_string(),
+        Key::KEY_VOLUMEDOWN => "AudioVolumeDown".to_string(),
+        Key::KEY_MUTE => "AudioVolumeMute".to_string(),
+        Key::KEY_MICMUTE => "MicMute".to_string(),
+        Key::KEY_PLAYPAUSE => "MediaPlayPause".to_string(),
+        Key::KEY_PLAYCD => "MediaPlay".to_string(),
+        Key::KEY_PAUSECD => "MediaPause".to_string(),
+        Key::KEY_STOPCD => "MediaStop".to_string(),
+        Key::KEY_NEXTSONG => "MediaTrackNext".to_string(),
+        Key::KEY_PREVIOUSSONG => "MediaTrackPrevious".to_string(),
+        Key::KEY_BRIGHTNESSUP => "BrightnessUp".to_string(),
+        Key::KEY_BRIGHTNESSDOWN => "BrightnessDown".to_string(),
+        Key::KEY_HOMEPAGE => "BrowserHome".to_string(),
+        Key::KEY_SEARCH => "BrowserSearch".to_string(),
+        Key::KEY_BACK => "BrowserBack".to_string(),
+        Key::KEY_FORWARD => "BrowserForward".to_string(),
+        Key::KEY_MAIL => "LaunchMail".to_string(),
+        Key::KEY_CALC => "LaunchCalculator".to_string(),
+
+        // The key next to right Ctrl on PC keyboards is KEY_COMPOSE
+        Key::KEY_COMPOSE => "ContextMenu".to_string(),
+        Key::KEY_MENU => "Menu".to_string(),
+
         // Other
         Key::KEY_SCROLLLOCK => "ScrollLock".to_string(),
         Key::KEY_PAUSE => "Pause".to_string(),
@@ -192,69 +516,185 @@ fn evdev_key_to_rdev_name(key: evdev::Key) -> String {
         // Fallback: use the Debug format but strip the "KEY_" prefix
         _ => {
             let debug_name = format!("{:?}", key);
-            if debug_name.starts_with("KEY_") {
-                debug_name[4..].to_string()
-            } else {
-                debug_name
+            match debug_name.strip_prefix("KEY_") {
+                Some(stripped) => stripped.to_string(),
+                None => debug_name,
             }
         }
     }
 }
 
-/// Output an error event to stdout in JSON format so the desktop app can read it
-/// The app typically only consumes stdout, so stderr errors may not be visible to users
+/// Check if a device has keyboard capabilities (has letter keys or modifier keys)
+/// Media keys often come from a separate "Consumer Control" device, so those count too
 #[cfg(target_os = "linux")]
-fn output_error_event(error_type: &str, message: &str) {
-    let error_event = KeyboardEvent {
-        event_type: "Error".to_string(),
-        name: Some(error_type.to_string()),
-        time: std::time::SystemTime::now(),
-        data: json!({"error": error_type, "message": message}).to_string(),
+fn is_keyboard_device(device: &evdev::Device) -> bool {
+    use evdev::Key;
+
+    device.supported_keys().is_some_and(|keys| {
+        keys.contains(Key::KEY_A) || keys.contains(Key::KEY_SPACE) ||
+        keys.contains(Key::KEY_LEFTCTRL) || keys.contains(Key::KEY_LEFTALT) ||
+        keys.contains(Key::KEY_VOLUMEUP) || keys.contains(Key::KEY_PLAYPAUSE) ||
+        keys.contains(Key::KEY_MICMUTE)
+    })
+}
+
+/// Check if a device is a mouse or touchpad (relative motion or a left button)
+#[cfg(target_os = "linux")]
+fn is_pointer_device(device: &evdev::Device) -> bool {
+    use evdev::{Key, RelativeAxisType};
+
+    device.supported_relative_axes().is_some_and(|axes| axes.contains(RelativeAxisType::REL_X))
+        || device.supported_keys().is_some_and(|keys| keys.contains(Key::BTN_LEFT))
+}
+
+/// Environment variables with comma-separated device names or paths,
+/// used when `--device`/`--exclude` aren't given
+#[cfg(target_os = "linux")]
+const DEVICES_ENV_VAR: &str = "SPEAKMCP_INPUT_DEVICES";
+#[cfg(target_os = "linux")]
+const EXCLUDE_DEVICES_ENV_VAR: &str = "SPEAKMCP_EXCLUDE_INPUT_DEVICES";
+
+/// Which input devices the listener may open
+#[cfg(target_os = "linux")]
+struct DeviceFilter {
+    /// Only these devices; None allows every device
+    allow: Option<Vec<String>>,
+    /// Never these devices, even if allowed
+    deny: Vec<String>,
+}
+
+#[cfg(target_os = "linux")]
+static DEVICE_FILTER: RwLock<DeviceFilter> = RwLock::new(DeviceFilter { allow: None, deny: Vec::new() });
+
+/// Bumped whenever the options deciding which devices are listenable change,
+/// so device threads know to re-check theirs
+#[cfg(target_os = "linux")]
+static DEVICE_FILTER_GENERATION: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+#[cfg(target_os = "linux")]
+fn configure_device_filter(options: &ListenOptions) {
+    let from_env = |var: &str| {
+        std::env::var(var).ok().map(|value| {
+            value.split(',').map(|spec| spec.trim().to_string()).filter(|spec| !spec.is_empty()).collect()
+        })
     };
-    // Output to stdout so the app can read it
-    println!("{}", serde_json::to_string(&error_event).unwrap());
-    // Also output to stderr for debugging
-    eprintln!("!error: {} - {}", error_type, message);
+    let allow = options.devices.clone().or_else(|| from_env(DEVICES_ENV_VAR));
+    let deny = options.exclude.clone().or_else(|| from_env(EXCLUDE_DEVICES_ENV_VAR)).unwrap_or_default();
+    *DEVICE_FILTER.write().unwrap() = DeviceFilter { allow, deny };
 }
 
+/// Whether a `--device`/`--exclude` value refers to this device
+/// Paths match exactly or through symlinks like /dev/input/by-id/*;
+/// anything else is a case-insensitive substring of the device name
 #[cfg(target_os = "linux")]
-fn start_keyboard_listener() -> Result<(), Box<dyn std::error::Error>> {
-    use evdev::{Device, Key};
-    use std::fs;
-    use std::path::PathBuf;
-    use std::thread;
-    use std::sync::atomic::{AtomicUsize, Ordering};
-    use std::sync::Arc;
+fn device_matches(spec: &str, path: &std::path::Path, device: &evdev::Device) -> bool {
+    if spec.starts_with('/') {
+        let spec = std::path::Path::new(spec);
+        return spec == path
+            || std::fs::canonicalize(spec).is_ok_and(|spec| std::fs::canonicalize(path).is_ok_and(|path| spec == path));
+    }
+    device.name().is_some_and(|name| name.to_lowercase().contains(&spec.to_lowercase()))
+}
+
+/// Whether the listener should open this device
+/// Pointer devices are only opened when mouse events are enabled
+#[cfg(target_os = "linux")]
+fn is_listenable_device(path: &std::path::Path, device: &evdev::Device) -> bool {
+    // Never listen to our own uinput clones of grabbed keyboards
+    if device.name() == Some(grab::VIRTUAL_DEVICE_NAME) {
+        return false;
+    }
+
+    let filter = DEVICE_FILTER.read().unwrap();
+    if filter.deny.iter().any(|spec| device_matches(spec, path, device)) {
+        return false;
+    }
+    let explicitly_allowed = match &filter.allow {
+        Some(allow) if allow.iter().any(|spec| device_matches(spec, path, device)) => true,
+        Some(_) => return false,
+        None => false,
+    };
+
+    // Devices asked for by name may be foot pedals or macro pads that don't
+    // look like keyboards, so any device with keys will do
+    is_keyboard_device(device)
+        || (explicitly_allowed && device.supported_keys().is_some_and(|keys| keys.iter().next().is_some()))
+        || (EMIT_MOUSE_EVENTS.load(Ordering::SeqCst) && is_pointer_device(device))
+}
+
+#[cfg(target_os = "linux")]
+const INPUT_DIR: &str = "/dev/input";
+
+/// Set of /dev/input paths that currently have a listener thread
+#[cfg(target_os = "linux")]
+type ActiveDevices = std::sync::Arc<std::sync::Mutex<std::collections::HashSet<std::path::PathBuf>>>;
+
+/// The running listener's devices, so a reload can open newly allowed ones
+#[cfg(target_os = "linux")]
+static LISTENER_DEVICES: std::sync::OnceLock<ActiveDevices> = std::sync::OnceLock::new();
+
+/// Spawn a listener thread for a keyboard device
+/// The path stays in `active` while the thread runs, so hotplug events for an
+/// already-monitored device are ignored, and is retired when the device stops
+#[cfg(target_os = "linux")]
+fn spawn_device_listener(path: std::path::PathBuf, device: evdev::Device, active: &ActiveDevices) {
+    let active = std::sync::Arc::clone(active);
+    if !active.lock().unwrap().insert(path.clone()) {
+        return;
+    }
+
+    std::thread::spawn(move || {
+        let failed = match listen_keyboard_device(&path, device) {
+            Ok(()) => {
+                eprintln!("Device {} no longer matches the listen options", path.display());
+                false
+            }
+            Err(e) => {
+                // Log the error but don't bring down the whole listener
+                // This allows hotkeys to continue working on other devices
+                // (e.g., if a USB keyboard is unplugged)
+                eprintln!("Device {} stopped: {}", path.display(), e);
+                true
+            }
+        };
+        let mut active = active.lock().unwrap();
+        active.remove(&path);
+        if failed && active.is_empty() {
+            // All devices have failed - output error to stdout so app can see it
+            output_error_event("AllDevicesFailed", "All keyboard devices have stopped");
+        }
+    });
+}
+
+/// Open every listenable device in /dev/input that has no listener yet
+/// Returns the last permission error, for the startup diagnostics
+#[cfg(target_os = "linux")]
+fn open_new_devices(active: &ActiveDevices) -> Result<Option<String>, String> {
+    use evdev::Device;
 
-    let input_dir = "/dev/input";
-    let mut last_error: Option<String> = None;
-    let mut keyboard_devices: Vec<(PathBuf, Device)> = Vec::new();
+    let mut last_error = None;
 
     // Enumerate devices in /dev/input/ to find ALL keyboards
-    let entries = fs::read_dir(input_dir)
-        .map_err(|e| format!("Cannot access {}: {}", input_dir, e))?;
+    let entries = std::fs::read_dir(INPUT_DIR)
+        .map_err(|e| format!("Cannot access {}: {}", INPUT_DIR, e))?;
 
     for entry in entries.filter_map(|e| e.ok()) {
         let path = entry.path();
         let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
 
         // Only look at eventN devices
-        if !name.starts_with("event") {
+        if !name.starts_with("event") || active.lock().unwrap().contains(&path) {
             continue;
         }
 
         // Try to open the device
         match Device::open(&path) {
             Ok(device) => {
-                // Check if this device has keyboard capabilities (has letter keys or modifier keys)
-                if device.supported_keys().map_or(false, |keys| {
-                    keys.contains(Key::KEY_A) || keys.contains(Key::KEY_SPACE) ||
-                    keys.contains(Key::KEY_LEFTCTRL) || keys.contains(Key::KEY_LEFTALT)
-                }) {
-                    eprintln!("Found keyboard: {} ({})",
+                if is_listenable_device(&path, &device) {
+                    eprintln!("Found input device: {} ({})",
                         device.name().unwrap_or("Unknown"),
                         path.display());
-                    keyboard_devices.push((path.clone(), device));
+                    spawn_device_listener(path, device, active);
                 }
             }
             Err(e) => {
@@ -264,138 +704,1266 @@ fn start_keyboard_listener() -> Result<(), Box<dyn std::error::Error>> {
             }
         }
     }
+    Ok(last_error)
+}
+
+#[cfg(target_os = "linux")]
+fn start_keyboard_listener() -> Result<(), Box<dyn std::error::Error>> {
+    let active = LISTENER_DEVICES.get_or_init(Default::default);
+    let last_error = open_new_devices(active)?;
+
+    let device_count = active.lock().unwrap().len();
 
     // No keyboard found - provide helpful error message
-    if keyboard_devices.is_empty() {
+    if device_count == 0 {
         if let Some(err) = last_error {
             let message = "User must be in 'input' group. Run: sudo usermod -aG input $USER, then log out and back in.";
             output_error_event("PermissionDenied", message);
             return Err(format!("Failed to access keyboard devices: {}", err).into());
         }
-        let message = "No keyboard device found in /dev/input/";
-        output_error_event("NoKeyboardFound", message);
-        return Err(message.into());
+        // Not fatal: a keyboard may still be plugged in or paired later
+        output_error_event("NoKeyboardFound", "No keyboard device found in /dev/input/");
+    } else {
+        eprintln!("Listening on {} keyboard device(s)", device_count);
     }
 
-    eprintln!("Listening on {} keyboard device(s)", keyboard_devices.len());
+    // Block here watching for keyboards that are connected later (USB, Bluetooth)
+    if let Err(e) = watch_for_new_devices(INPUT_DIR, active) {
+        eprintln!("Keyboard hotplug detection unavailable: {}", e);
+    }
 
-    // If only one keyboard, no need for threading
-    if keyboard_devices.len() == 1 {
-        let (_, device) = keyboard_devices.into_iter().next().unwrap();
-        return listen_keyboard_device(device);
+    // Without hotplug detection, keep running until every device has stopped
+    loop {
+        if active.lock().unwrap().is_empty() {
+            return Err("All keyboard devices have stopped".into());
+        }
+        std::thread::sleep(std::time::Duration::from_secs(60));
     }
+}
 
-    // Multiple keyboards: spawn a thread for each
-    // Track how many devices are still active - treat per-device failures as non-fatal
-    let active_count = Arc::new(AtomicUsize::new(keyboard_devices.len()));
+/// Watch /dev/input with inotify and start listening on new keyboards as they appear
+/// Only returns if the watch can't be set up or reading from it fails
+#[cfg(target_os = "linux")]
+fn watch_for_new_devices(input_dir: &str, active: &ActiveDevices) -> Result<(), Box<dyn std::error::Error>> {
+    use evdev::Device;
+    use inotify::{Inotify, WatchMask};
+    use std::path::Path;
 
-    for (path, device) in keyboard_devices {
-        let active_count = Arc::clone(&active_count);
-        let path_str = path.display().to_string();
-        thread::spawn(move || {
-            if let Err(e) = listen_keyboard_device(device) {
-                // Log the error but don't bring down the whole listener
-                // This allows hotkeys to continue working on other devices
-                // (e.g., if a USB keyboard is unplugged)
-                eprintln!("Device {} stopped: {}", path_str, e);
-                let remaining = active_count.fetch_sub(1, Ordering::SeqCst) - 1;
-                if remaining == 0 {
-                    // All devices have failed - output error to stdout so app can see it
-                    output_error_event("AllDevicesFailed", "All keyboard devices have stopped");
+    let mut inotify = Inotify::init()?;
+    // udev creates the node first and fixes up its permissions afterwards,
+    // so a device that can't be opened on CREATE is retried on ATTRIB
+    inotify.watches().add(input_dir, WatchMask::CREATE | WatchMask::ATTRIB)?;
+
+    let mut buffer = [0u8; 4096];
+    loop {
+        for event in inotify.read_events_blocking(&mut buffer)? {
+            let Some(name) = event.name.and_then(|n| n.to_str()) else {
+                continue;
+            };
+            if !name.starts_with("event") {
+                continue;
+            }
+
+            let path = Path::new(input_dir).join(name);
+            if active.lock().unwrap().contains(&path) {
+                continue;
+            }
+
+            // Errors are expected here (permissions not applied yet, device
+            // already gone again), so just wait for the next event
+            if let Ok(device) = Device::open(&path) {
+                if is_listenable_device(&path, &device) {
+                    eprintln!("Input device connected: {} ({})",
+                        device.name().unwrap_or("Unknown"),
+                        path.display());
+                    spawn_device_listener(path, device, active);
                 }
             }
-        });
+        }
     }
+}
 
-    // Block the main thread forever - the spawned threads will handle events
-    // This prevents the function from returning while devices are still being monitored
-    loop {
-        thread::sleep(std::time::Duration::from_secs(60));
-        // Check if all devices have failed
-        if active_count.load(Ordering::SeqCst) == 0 {
-            return Err("All keyboard devices have stopped".into());
-        }
+/// Map evdev mouse buttons to rdev's Button names
+#[cfg(target_os = "linux")]
+fn evdev_button_to_rdev_name(key: evdev::Key) -> Option<&'static str> {
+    use evdev::Key;
+    match key {
+        Key::BTN_LEFT => Some("Left"),
+        Key::BTN_RIGHT => Some("Right"),
+        Key::BTN_MIDDLE => Some("Middle"),
+        _ => None,
     }
 }
 
 #[cfg(target_os = "linux")]
-fn listen_keyboard_device(mut device: evdev::Device) -> Result<(), Box<dyn std::error::Error>> {
-    use evdev::InputEventKind;
+/// Listen on one device until it fails, or returns Ok once reloaded options
+/// no longer allow it
+fn listen_keyboard_device(path: &std::path::Path, mut device: evdev::Device) -> Result<(), Box<dyn std::error::Error>> {
+    use evdev::{InputEventKind, RelativeAxisType, Synchronization};
+
+    let mut filter_generation = DEVICE_FILTER_GENERATION.load(Ordering::SeqCst);
+
+    // Relative motion arrives as separate REL_X/REL_Y events per frame;
+    // accumulate them and emit one MouseMove per SYN_REPORT
+    let mut motion = (0i32, 0i32);
+
+    let mut grabbed = None;
+    if grab::enabled() && is_keyboard_device(&device) {
+        match grab::grab_device(&mut device) {
+            Ok(grabbed_device) => grabbed = Some(grabbed_device),
+            Err(e) => eprintln!(
+                "Failed to grab {}: {} (hotkeys will also reach other apps)",
+                device.name().unwrap_or("Unknown"),
+                e
+            ),
+        }
+    }
 
     loop {
-        for event in device.fetch_events()? {
-            if let InputEventKind::Key(key) = event.kind() {
-                let event_type = match event.value() {
-                    0 => "KeyRelease",
-                    1 => "KeyPress",
-                    2 => continue, // Key repeat, skip
-                    _ => continue,
-                };
+        let mut reinject_failed = false;
+        let events: Vec<evdev::InputEvent> = device.fetch_events()?.collect();
 
-                // Convert evdev key name to rdev-compatible format
-                let rdev_key_name = evdev_key_to_rdev_name(key);
+        let generation = DEVICE_FILTER_GENERATION.load(Ordering::SeqCst);
+        if generation != filter_generation {
+            filter_generation = generation;
+            if !is_listenable_device(path, &device) {
+                return Ok(());
+            }
+        }
 
-                let json_event = KeyboardEvent {
-                    event_type: event_type.to_string(),
-                    name: Some(rdev_key_name.clone()),
-                    time: std::time::SystemTime::now(),
-                    data: json!({"key": rdev_key_name}).to_string(),
+        for event in events {
+            if let Some(grabbed_device) = grabbed.as_mut() {
+                let key_name = match event.kind() {
+                    InputEventKind::Key(key) => Some(evdev_key_to_rdev_name(key)),
+                    _ => None,
                 };
+                if let Err(e) = grabbed_device.handle(&event, key_name.as_deref()) {
+                    eprintln!("Failed to re-inject input events: {}", e);
+                    reinject_failed = true;
+                }
+            }
+
+            if !EMIT_INPUT_EVENTS.load(Ordering::SeqCst) {
+                continue;
+            }
+            let emit_mouse = EMIT_MOUSE_EVENTS.load(Ordering::SeqCst);
+
+            match event.kind() {
+                InputEventKind::Key(key) => {
+                    let event_type = match event.value() {
+                        0 => "KeyRelease",
+                        1 => "KeyPress",
+                        2 => continue, // Key repeat, skip
+                        _ => continue,
+                    };
+
+                    // Buttons are never keys, even with mouse events off (pointer
+                    // devices opened earlier or asked for with --device still send them)
+                    if let Some(button) = evdev_button_to_rdev_name(key) {
+                        if emit_mouse {
+                            let event_type = if event_type == "KeyPress" { "ButtonPress" } else { "ButtonRelease" };
+                            output_event(&KeyboardEvent {
+                                event_type: event_type.to_string(),
+                                name: None,
+                                time: std::time::SystemTime::now(),
+                                elapsed_ms: elapsed_ms(),
+                                data: json!({"button": button}).to_string(),
+                            });
+                        }
+                        continue;
+                    }
 
-                println!("{}", serde_json::to_string(&json_event).unwrap());
+                    // Convert evdev key name to rdev-compatible format
+                    let rdev_key_name = evdev_key_to_rdev_name(key);
+                    if !key_passes_filter(&rdev_key_name) {
+                        continue;
+                    }
+
+                    // evdev codes are physical; add what the key means in the user's layout
+                    let mut data = json!({"key": rdev_key_name});
+                    if let Some(resolved) = layout::resolve(key.code()) {
+                        data["layout_key"] = json!(resolved.layout_key.as_deref().unwrap_or(&rdev_key_name));
+                        data["keysym"] = json!(resolved.keysym);
+                        data["char"] = json!(resolved.char);
+                    }
+
+                    let json_event = KeyboardEvent {
+                        event_type: event_type.to_string(),
+                        name: Some(rdev_key_name.clone()),
+                        time: std::time::SystemTime::now(),
+                        elapsed_ms: elapsed_ms(),
+                        data: data.to_string(),
+                    };
+                    output_key_event(json_event, &rdev_key_name, event_type == "KeyPress");
+                }
+                InputEventKind::RelAxis(axis) if emit_mouse => match axis {
+                    RelativeAxisType::REL_X => motion.0 += event.value(),
+                    RelativeAxisType::REL_Y => motion.1 += event.value(),
+                    RelativeAxisType::REL_WHEEL | RelativeAxisType::REL_HWHEEL => {
+                        let (delta_x, delta_y) = if axis == RelativeAxisType::REL_WHEEL {
+                            (0, event.value())
+                        } else {
+                            (event.value(), 0)
+                        };
+                        output_event(&KeyboardEvent {
+                            event_type: "Wheel".to_string(),
+                            name: None,
+                            time: std::time::SystemTime::now(),
+                            elapsed_ms: elapsed_ms(),
+                            data: json!({"delta_x": delta_x, "delta_y": delta_y}).to_string(),
+                        });
+                    }
+                    _ => {}
+                },
+                InputEventKind::Synchronization(Synchronization::SYN_REPORT) if motion != (0, 0) => {
+                    // evdev only reports relative motion, unlike rdev's absolute
+                    // screen coordinates, so Linux MouseMove events carry deltas
+                    output_event(&KeyboardEvent {
+                        event_type: "MouseMove".to_string(),
+                        name: None,
+                        time: std::time::SystemTime::now(),
+                        elapsed_ms: elapsed_ms(),
+                        data: json!({"delta_x": motion.0, "delta_y": motion.1}).to_string(),
+                    });
+                    motion = (0, 0);
+                }
+                _ => {}
             }
         }
+
+        // Never keep a keyboard grabbed that we can't pass events through for
+        if reinject_failed && grabbed.take().is_some() {
+            device.ungrab()?;
+        }
     }
 }
 
 // ============ Common functions ============
 
-fn write_text(text: &str) -> Result<(), Box<dyn std::error::Error>> {
-    use enigo::{Enigo, Keyboard, Settings};
+/// Output an error event to stdout in JSON format so the desktop app can read it
+/// The app typically only consumes stdout, so stderr errors may not be visible to users
+fn error_event(error_type: &str, message: &str) -> KeyboardEvent {
+    KeyboardEvent {
+        event_type: "Error".to_string(),
+        name: Some(error_type.to_string()),
+        time: std::time::SystemTime::now(),
+        elapsed_ms: elapsed_ms(),
+        data: json!({"error": error_type, "message": message}).to_string(),
+    }
+}
 
-    let mut enigo = match Enigo::new(&Settings::default()) {
-        Ok(enigo) => enigo,
-        Err(e) => {
-            eprintln!("Failed to create Enigo instance: {}", e);
-            return Err(Box::new(e));
+fn output_error_event(error_type: &str, message: &str) {
+    // Output to stdout so the app can read it
+    output_event(&error_event(error_type, message));
+    // Also output to stderr for debugging
+    eprintln!("!error: {} - {}", error_type, message);
+}
+
+/// Features this build supports, reported in the ready handshake
+fn capabilities() -> Vec<&'static str> {
+    let capabilities = vec!["listen", "serve", "write", "press", "clipboard", "mouse", "holds", "key_filter", "log_file", "msgpack", "focus", "reload_config"];
+    #[cfg(target_os = "linux")]
+    let capabilities = {
+        let mut capabilities = capabilities;
+        capabilities.extend(["grab", "devices", "uinput", "wayland"]);
+        if xkbcommon_dl::xkbcommon_option().is_some() {
+            capabilities.push("layout");
         }
+        capabilities
     };
+    capabilities
+}
+
+/// Print a one-line handshake before any events so the host can check the
+/// binary's version and features instead of parsing stderr
+/// Deliberately not a KeyboardEvent: hosts that only understand key events skip it
+fn output_ready_event() {
+    let backend = if cfg!(target_os = "linux") { "evdev" } else { "rdev" };
+    let ready = json!({
+        "type": "ready",
+        "version": env!("CARGO_PKG_VERSION"),
+        "backend": backend,
+        "capabilities": capabilities(),
+        // Wall-clock time (Unix ms) at which elapsed_ms was 0
+        "epoch_ms": epoch().1.duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64),
+    });
+    output::emit(&ready);
+}
+
+/// Stop emitting events, release grabbed keyboards and exit
+/// The final `{"type":"shutdown"}` line tells the host the exit was deliberate
+fn shutdown() -> ! {
+    EMIT_INPUT_EVENTS.store(false, Ordering::SeqCst);
+    #[cfg(target_os = "linux")]
+    grab::release_all();
+
+    output::emit(&json!({"type": "shutdown"}));
+    output::flush();
+    // Hold the lock so no listener thread writes after the shutdown line
+    let _stdout = std::io::stdout().lock();
+    std::process::exit(0);
+}
 
-    match enigo.text(text) {
-        Ok(_) => Ok(()),
+/// Shut down cleanly on SIGINT/SIGTERM instead of being killed mid-grab,
+/// and reload the listen config on SIGHUP
+#[cfg(unix)]
+fn install_signal_handlers() {
+    use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+
+    let mut signals = match signal_hook::iterator::Signals::new([SIGINT, SIGTERM, SIGHUP]) {
+        Ok(signals) => signals,
         Err(e) => {
-            eprintln!("Failed to write text: {}", e);
-            Err(Box::new(e))
+            eprintln!("!error: failed to install signal handler: {}", e);
+            return;
+        }
+    };
+    std::thread::spawn(move || {
+        for signal in signals.forever() {
+            match signal {
+                SIGHUP => reload_listen_config(),
+                _ => shutdown(),
+            }
+        }
+    });
+}
+
+/// Shut down cleanly on Ctrl+C or when the console closes
+#[cfg(windows)]
+fn install_signal_handlers() {
+    if let Err(e) = ctrlc::set_handler(|| shutdown()) {
+        eprintln!("!error: failed to install signal handler: {}", e);
+    }
+}
+
+/// How `write` injects text into the focused app
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum WriteMethod {
+    /// Type the text key by key with enigo
+    #[default]
+    Type,
+    /// Paste the text via the clipboard, restoring its previous contents
+    Clipboard,
+}
+
+impl std::str::FromStr for WriteMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "type" => Ok(WriteMethod::Type),
+            "clipboard" => Ok(WriteMethod::Clipboard),
+            _ => Err(format!("Unknown write method '{}' (expected type or clipboard)", s)),
         }
     }
 }
 
+/// Which injector types the text for the `type` method
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum WriteBackend {
+    /// On Wayland sessions: the virtual-keyboard protocol, then uinput,
+    /// then enigo, whichever works first. enigo everywhere else. uinput is
+    /// only used when the keyboard layout can be read to map characters.
+    #[default]
+    Auto,
+    Enigo,
+    /// Virtual uinput keyboard, mapped through the keyboard layout (Linux only)
+    Uinput,
+    /// virtual-keyboard-unstable-v1, e.g. Sway and Hyprland (Linux only)
+    Wayland,
+}
+
+impl std::str::FromStr for WriteBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(WriteBackend::Auto),
+            "enigo" => Ok(WriteBackend::Enigo),
+            "uinput" => Ok(WriteBackend::Uinput),
+            "wayland" => Ok(WriteBackend::Wayland),
+            _ => Err(format!("Unknown write backend '{}' (expected auto, enigo, uinput or wayland)", s)),
+        }
+    }
+}
+
+/// Long texts are typed in chunks of this many characters
+const DEFAULT_CHUNK_SIZE: usize = 256;
+
+/// Pause between chunks so the target app can drain its input queue
+const CHUNK_FLUSH_DELAY: std::time::Duration = std::time::Duration::from_millis(20);
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct WriteOptions {
+    method: WriteMethod,
+    backend: WriteBackend,
+    /// Delay between typed characters, for apps that drop keys at full speed
+    delay_ms: u64,
+    /// Characters typed per chunk before flushing; 0 types everything at once
+    chunk_size: usize,
+    /// Let typing fall back to a clipboard paste, which replaces the user's
+    /// clipboard for a moment and can't restore images
+    clipboard_fallback: bool,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        WriteOptions {
+            method: WriteMethod::default(),
+            backend: WriteBackend::default(),
+            delay_ms: 0,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            clipboard_fallback: false,
+        }
+    }
+}
+
+/// Where `write` reads long text from instead of argv, which has length
+/// limits and mangles newlines and quotes on Windows
+enum TextSource {
+    Stdin,
+    File(String),
+}
+
+impl TextSource {
+    /// Read the text verbatim; a trailing newline is typed like any other
+    fn read(&self) -> Result<String, String> {
+        match self {
+            TextSource::Stdin => {
+                let mut text = String::new();
+                std::io::Read::read_to_string(&mut std::io::stdin(), &mut text)
+                    .map_err(|e| format!("Failed to read text from stdin: {}", e))?;
+                Ok(text)
+            }
+            TextSource::File(path) => std::fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read text from {}: {}", path, e)),
+        }
+    }
+}
+
+/// Parse `write [--method type|clipboard] [--backend auto|enigo|uinput] [--delay-ms <n>] [--chunk-size <n>] [--clipboard-fallback] <text>`,
+/// with `--stdin` or `--file <path>` in place of `<text>`.
+/// Options must come before the text; `--` ends option parsing.
+fn parse_write_args(args: &[String]) -> Result<(WriteOptions, String), String> {
+    let mut options = WriteOptions::default();
+    let mut source = None;
+    let mut iter = args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--method" => {
+                let value = iter.next().ok_or("--method requires a value")?;
+                options.method = value.parse()?;
+            }
+            "--backend" => {
+                let value = iter.next().ok_or("--backend requires a value")?;
+                options.backend = value.parse()?;
+            }
+            "--delay-ms" => {
+                let value = iter.next().ok_or("--delay-ms requires a value")?;
+                options.delay_ms = value
+                    .parse()
+                    .map_err(|_| format!("Invalid --delay-ms value '{}'", value))?;
+            }
+            "--chunk-size" => {
+                let value = iter.next().ok_or("--chunk-size requires a value")?;
+                options.chunk_size = value
+                    .parse()
+                    .map_err(|_| format!("Invalid --chunk-size value '{}'", value))?;
+            }
+            "--clipboard-fallback" => options.clipboard_fallback = true,
+            "--stdin" | "--file" if source.is_some() => {
+                return Err("Only one of --stdin and --file can be given".to_string());
+            }
+            "--stdin" => source = Some(TextSource::Stdin),
+            "--file" => {
+                let value = iter.next().ok_or("--file requires a path")?;
+                source = Some(TextSource::File(value.clone()));
+            }
+            _ if source.is_some() => {
+                return Err(format!("Unexpected argument '{}' with --stdin/--file", arg));
+            }
+            "--" => {
+                let text = iter.next().ok_or("Missing text to write")?;
+                return Ok((options, text.clone()));
+            }
+            _ => return Ok((options, arg.clone())),
+        }
+    }
+    match source {
+        Some(source) => Ok((options, source.read()?)),
+        None => Err("Missing text to write".to_string()),
+    }
+}
+
+/// How a `write` got its text into the focused app, for the JSON result
+#[derive(Serialize)]
+struct WriteReport {
+    /// The method that succeeded: unicode, clipboard, compose, uinput or wayland
+    method: &'static str,
+    /// Methods tried before it, with why they failed
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    failed: Vec<FailedMethod>,
+}
+
+#[derive(Serialize)]
+struct FailedMethod {
+    method: &'static str,
+    error: String,
+}
+
+impl WriteReport {
+    fn new(method: &'static str) -> Self {
+        WriteReport { method, failed: Vec::new() }
+    }
+
+    fn after(mut self, mut failed: Vec<FailedMethod>) -> Self {
+        failed.append(&mut self.failed);
+        self.failed = failed;
+        self
+    }
+}
+
+fn write(text: &str, options: &WriteOptions) -> Result<WriteReport, Box<dyn std::error::Error>> {
+    match options.method {
+        WriteMethod::Type => type_text(text, options),
+        WriteMethod::Clipboard => clipboard::paste_text(text).map(|()| WriteReport::new("clipboard")),
+    }
+}
+
+/// Type text with the configured backend
+fn type_text(text: &str, options: &WriteOptions) -> Result<WriteReport, Box<dyn std::error::Error>> {
+    match options.backend {
+        WriteBackend::Enigo => write_text(text, options),
+        #[cfg(target_os = "linux")]
+        WriteBackend::Uinput => {
+            let keys = match uinput_layout_keys()? {
+                Some(keys) => keys,
+                None => {
+                    eprintln!("libxkbcommon not found, typing with a US layout; other layouts will get wrong characters");
+                    uinput::us_keys()
+                }
+            };
+            uinput::VirtualKeyboard::new(keys)?.write_text(text, options)?;
+            Ok(WriteReport::new("uinput"))
+        }
+        #[cfg(target_os = "linux")]
+        WriteBackend::Wayland => {
+            wayland::VirtualKeyboard::new()?.write_text(text, options)?;
+            Ok(WriteReport::new("wayland"))
+        }
+        #[cfg(not(target_os = "linux"))]
+        WriteBackend::Uinput | WriteBackend::Wayland => {
+            Err("The uinput and wayland backends are only supported on Linux".into())
+        }
+        #[cfg(target_os = "linux")]
+        WriteBackend::Auto => {
+            if !uinput::is_wayland_session() {
+                return write_text(text, options);
+            }
+            let mut failed = Vec::new();
+            // What's left to type after a backend fails partway through
+            let mut rest = text;
+            match wayland::VirtualKeyboard::new() {
+                Ok(mut keyboard) => match keyboard.write_text(rest, options) {
+                    Ok(()) => return Ok(WriteReport::new("wayland")),
+                    Err(e) => {
+                        eprintln!("Wayland virtual keyboard failed ({}), trying uinput", e);
+                        failed.push(FailedMethod { method: "wayland", error: e.to_string() });
+                        rest = &rest[keyboard.typed()..];
+                    }
+                },
+                Err(e) => {
+                    eprintln!("Wayland virtual keyboard unavailable ({}), trying uinput", e);
+                    failed.push(FailedMethod { method: "wayland", error: e.to_string() });
+                }
+            }
+            // Without the layout, typing with uinput would be garbled
+            let keys = match uinput_layout_keys() {
+                Ok(Some(keys)) => keys,
+                Ok(None) => {
+                    let error = "libxkbcommon is needed to map text to the keyboard layout".to_string();
+                    failed.push(FailedMethod { method: "uinput", error });
+                    return write_text(rest, options).map(|report| report.after(failed));
+                }
+                Err(error) => {
+                    failed.push(FailedMethod { method: "uinput", error });
+                    return write_text(rest, options).map(|report| report.after(failed));
+                }
+            };
+            if let Err(error) = uinput::check_typeable(rest, &keys) {
+                failed.push(FailedMethod { method: "uinput", error });
+                return write_text(rest, options).map(|report| report.after(failed));
+            }
+            match uinput::VirtualKeyboard::new(keys) {
+                Ok(mut keyboard) => match keyboard.write_text(rest, options) {
+                    Ok(()) => return Ok(WriteReport::new("uinput").after(failed)),
+                    Err(e) => {
+                        eprintln!("uinput typing failed ({}), falling back to enigo", e);
+                        failed.push(FailedMethod { method: "uinput", error: e.to_string() });
+                        rest = &rest[keyboard.typed()..];
+                    }
+                },
+                Err(e) => {
+                    eprintln!("uinput unavailable ({}), falling back to enigo", e);
+                    failed.push(FailedMethod { method: "uinput", error: e.to_string() });
+                }
+            }
+            write_text(rest, options).map(|report| report.after(failed))
+        }
+        #[cfg(not(target_os = "linux"))]
+        WriteBackend::Auto => write_text(text, options),
+    }
+}
+
+/// uinput keycodes go through the user's layout, so characters have to be
+/// mapped through it too; None when libxkbcommon isn't installed
+#[cfg(target_os = "linux")]
+fn uinput_layout_keys() -> Result<Option<uinput::CharKeys>, String> {
+    Ok(layout::compile(None)?.map(|keymap| uinput::layout_keys(&keymap)))
+}
+
+/// Feed `text` to `type_str` in chunks, honoring the chunk size and the
+/// per-character delay (which types one character per call)
+fn type_in_chunks<E>(
+    text: &str,
+    options: &WriteOptions,
+    mut type_str: impl FnMut(&str) -> Result<(), E>,
+) -> Result<(), E> {
+    use std::thread;
+    use std::time::Duration;
+
+    let chars: Vec<char> = text.chars().collect();
+    let chunk_size = if options.chunk_size == 0 { chars.len().max(1) } else { options.chunk_size };
+    let char_delay = Duration::from_millis(options.delay_ms);
+
+    for (index, chunk) in chars.chunks(chunk_size).enumerate() {
+        if index > 0 {
+            thread::sleep(CHUNK_FLUSH_DELAY);
+        }
+
+        if char_delay.is_zero() {
+            type_str(&chunk.iter().collect::<String>())?;
+        } else {
+            for c in chunk {
+                type_str(c.encode_utf8(&mut [0; 4]))?;
+                thread::sleep(char_delay);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Type text with enigo's native unicode input, falling back to a clipboard
+/// paste (only with `clipboard_fallback`) and then (on Linux) Ctrl+Shift+U
+/// sequences for whatever is left. Each fallback picks up after the
+/// characters already typed.
+fn write_text(text: &str, options: &WriteOptions) -> Result<WriteReport, Box<dyn std::error::Error>> {
+    use enigo::{Enigo, Keyboard, Settings};
+
+    let mut failed = Vec::new();
+    let mut typed = 0;
+    let result = Enigo::new(&Settings::default())
+        .map_err(|e| e.to_string())
+        .and_then(|mut enigo| {
+            type_in_chunks(text, options, |chunk| {
+                // One character at a time, so a failure partway through a chunk
+                // doesn't make the fallback retype what already went through
+                for c in chunk.chars() {
+                    enigo.text(c.encode_utf8(&mut [0; 4]))?;
+                    typed += c.len_utf8();
+                }
+                Ok::<(), enigo::InputError>(())
+            })
+            .map_err(|e| e.to_string())
+        });
+    match result {
+        Ok(()) => return Ok(WriteReport::new("unicode")),
+        Err(error) => {
+            eprintln!("Failed to write text: {}", error);
+            failed.push(FailedMethod { method: "unicode", error });
+        }
+    }
+
+    let rest = &text[typed..];
+    if options.clipboard_fallback {
+        match clipboard::paste_text(rest) {
+            Ok(()) => return Ok(WriteReport::new("clipboard").after(failed)),
+            Err(e) => {
+                eprintln!("Clipboard paste failed: {}", e);
+                failed.push(FailedMethod { method: "clipboard", error: e.to_string() });
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    match compose::type_text(rest) {
+        Ok(()) => return Ok(WriteReport::new("compose").after(failed)),
+        Err(e) => failed.push(FailedMethod { method: "compose", error: e.to_string() }),
+    }
+
+    let errors: Vec<String> = failed.iter().map(|f| format!("{}: {}", f.method, f.error)).collect();
+    Err(format!("All input methods failed ({})", errors.join("; ")).into())
+}
+
+// ============ Stdin command protocol (serve mode) ============
+// Long-running mode for the desktop app: one JSON command per line on stdin,
+// e.g. {"type":"write","text":"hello"}. Every command is answered with a
+// "CommandResult" event on stdout, using the same envelope as key events.
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StdinCommand {
+    Write {
+        text: String,
+        #[serde(flatten)]
+        options: WriteOptions,
+    },
+    Press { combo: String },
+    ListenStart {
+        #[serde(flatten)]
+        options: ListenOptions,
+    },
+    ListenStop,
+    /// Swap in new listen options without restarting the listener or unmuting it
+    ReloadConfig {
+        #[serde(flatten)]
+        options: ListenOptions,
+    },
+    Shutdown,
+}
+
+#[derive(Deserialize)]
+struct StdinRequest {
+    /// Optional caller-chosen id, echoed back in the result
+    #[serde(default)]
+    id: Option<serde_json::Value>,
+    #[serde(flatten)]
+    command: StdinCommand,
+}
+
+impl StdinCommand {
+    fn name(&self) -> &'static str {
+        match self {
+            StdinCommand::Write { .. } => "write",
+            StdinCommand::Press { .. } => "press",
+            StdinCommand::ListenStart { .. } => "listen_start",
+            StdinCommand::ListenStop => "listen_stop",
+            StdinCommand::ReloadConfig { .. } => "reload_config",
+            StdinCommand::Shutdown => "shutdown",
+        }
+    }
+}
+
+fn output_command_result(
+    command: &str,
+    id: Option<serde_json::Value>,
+    result: Result<(), String>,
+) {
+    output_command_result_with(command, id, result.map(|()| serde_json::Map::new()));
+}
+
+/// Like `output_command_result`, with extra fields merged into a successful result
+fn output_command_result_with(
+    command: &str,
+    id: Option<serde_json::Value>,
+    result: Result<serde_json::Map<String, serde_json::Value>, String>,
+) {
+    let data = match result {
+        Ok(mut fields) => {
+            fields.extend([
+                ("command".to_string(), json!(command)),
+                ("id".to_string(), json!(id)),
+                ("success".to_string(), json!(true)),
+            ]);
+            serde_json::Value::Object(fields)
+        }
+        Err(error) => json!({"command": command, "id": id, "success": false, "error": error}),
+    };
+    let result_event = KeyboardEvent {
+        event_type: "CommandResult".to_string(),
+        name: Some(command.to_string()),
+        time: std::time::SystemTime::now(),
+        elapsed_ms: elapsed_ms(),
+        data: data.to_string(),
+    };
+    output_event(&result_event);
+}
+
+/// `takeover` applies to the instance lock taken when listen_start first starts the listener
+fn run_stdin_commands(takeover: bool) {
+    use std::io::BufRead;
+
+    // Key events stay muted until the host asks for them
+    EMIT_INPUT_EVENTS.store(false, Ordering::SeqCst);
+    output_ready_event();
+    let mut listener_started = false;
+
+    for line in std::io::stdin().lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                eprintln!("!error: failed to read stdin: {}", e);
+                break;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        // Parsed in two steps so a bad command still gets its id echoed back
+        let request = serde_json::from_str::<serde_json::Value>(&line)
+            .map_err(|e| (None, e))
+            .and_then(|value| {
+                let id = value.get("id").cloned();
+                serde_json::from_value::<StdinRequest>(value).map_err(|e| (id, e))
+            });
+        let request = match request {
+            Ok(request) => request,
+            Err((id, e)) => {
+                output_command_result("unknown", id, Err(format!("Invalid command: {}", e)));
+                continue;
+            }
+        };
+        let name = request.command.name();
+        crash::record_message("in", name, line.len());
+
+        match request.command {
+            StdinCommand::Write { text, options } => {
+                let result = write(&text, &options).map_err(|e| e.to_string()).map(|report| {
+                    match serde_json::to_value(report) {
+                        Ok(serde_json::Value::Object(fields)) => fields,
+                        _ => serde_json::Map::new(),
+                    }
+                });
+                output_command_result_with(name, request.id, result);
+            }
+            StdinCommand::Press { combo } => {
+                let result = press::parse_combo(&combo)
+                    .and_then(|combo| press::press_combo(&combo).map_err(|e| e.to_string()));
+                output_command_result(name, request.id, result);
+            }
+            StdinCommand::ListenStart { options } => {
+                if let Err(e) = instance::acquire(takeover) {
+                    output_command_result(name, request.id, Err(e));
+                    continue;
+                }
+                // On Linux, mouse devices are only opened (and keyboards only
+                // grabbed) per the options in effect when the listener started
+                // or the device was connected
+                if let Err(e) = apply_listen_options(&options) {
+                    output_command_result(name, request.id, Err(e));
+                    continue;
+                }
+                // The platform listeners block forever and can't be torn down,
+                // so the listener is started once and listen_stop only mutes it
+                if !listener_started {
+                    listener_started = true;
+                    std::thread::spawn(|| {
+                        if let Err(error) = start_keyboard_listener() {
+                            eprintln!("!error: {}", error);
+                            output_error_event("ListenerStopped", &error.to_string());
+                        }
+                    });
+                }
+                holds::reset();
+                EMIT_INPUT_EVENTS.store(true, Ordering::SeqCst);
+                output_command_result(name, request.id, Ok(()));
+            }
+            StdinCommand::ListenStop => {
+                EMIT_INPUT_EVENTS.store(false, Ordering::SeqCst);
+                holds::reset();
+                output_command_result(name, request.id, Ok(()));
+            }
+            StdinCommand::ReloadConfig { options } => {
+                output_command_result(name, request.id, apply_listen_options(&options));
+            }
+            StdinCommand::Shutdown => {
+                output_command_result(name, request.id, Ok(()));
+                break;
+            }
+        }
+    }
+    // Also reached when stdin closes: the host went away, so there is nobody left to talk to
+    shutdown();
+}
+
+/// Parse and run `permissions [--prompt]`, printing the macOS permission state as JSON
+fn run_permissions(args: &[String]) -> Result<(), String> {
+    let mut prompt = false;
+    for arg in args {
+        match arg.as_str() {
+            "--prompt" => prompt = true,
+            _ => return Err(format!("Unknown permissions option '{}'", arg)),
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        let state = if prompt { permissions::request() } else { permissions::query() };
+        println!(
+            "{}",
+            json!({
+                "accessibility": state.accessibility,
+                "input_monitoring": state.input_monitoring,
+                "post_events": state.post_events,
+                "prompted": prompt,
+            })
+        );
+        Ok(())
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        let _ = prompt;
+        Err("permissions is only supported on macOS; use doctor on other platforms".to_string())
+    }
+}
+
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
+    epoch();
+    crash::install();
+    let mut args: Vec<String> = std::env::args().collect();
+    let mut takeover = false;
+
+    if args.len() > 1 && (args[1] == "listen" || args[1] == "serve") {
+        // --format applies to everything either mode writes, so it's handled before both
+        if let Some(i) = args.iter().position(|arg| arg == "--format") {
+            let result = args
+                .get(i + 1)
+                .ok_or_else(|| "--format requires json or msgpack".to_string())
+                .and_then(|format| output::set_format(format));
+            if let Err(e) = result {
+                eprintln!("!error: {}", e);
+                std::process::exit(1);
+            }
+            args.drain(i..=i + 1);
+        }
 
-    if args.len() > 1 && args[1] == "listen" {
+        // Only one listener may run at a time, or every key is reported twice
+        takeover = args.iter().any(|arg| arg == "--takeover");
+        args.retain(|arg| arg != "--takeover");
+    }
+
+    if args.len() > 1 && args[1] == "serve" {
+        install_signal_handlers();
+        run_stdin_commands(takeover);
+    } else if args.len() > 1 && args[1] == "listen" {
+        if let Err(e) = instance::acquire(takeover) {
+            output_error_event("AlreadyRunning", &e);
+            output::flush();
+            std::process::exit(1);
+        }
+        match parse_listen_args(&args[2..]).and_then(|options| apply_listen_options(&options)) {
+            Ok(()) => {}
+            Err(e) => {
+                eprintln!("!error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        let _ = LISTEN_ARGS.set(args[2..].to_vec());
+        install_signal_handlers();
+        output_ready_event();
         if let Err(error) = start_keyboard_listener() {
             eprintln!("!error: {}", error);
+            output::flush();
             std::process::exit(1);
         }
     } else if args.len() > 2 && args[1] == "write" {
-        let text = args[2].clone();
+        let (options, text) = match parse_write_args(&args[2..]) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                eprintln!("Write command failed: {}", e);
+                std::process::exit(1);
+            }
+        };
 
-        match write_text(text.as_str()) {
-            Ok(_) => {
+        match write(text.as_str(), &options) {
+            Ok(report) => {
+                let mut result = serde_json::to_value(&report).unwrap_or_default();
+                result["success"] = json!(true);
+                println!("{}", result);
                 std::process::exit(0);
             },
             Err(e) => {
                 eprintln!("Write command failed: {}", e);
+                println!("{}", json!({"success": false, "error": e.to_string()}));
+                std::process::exit(101);
+            }
+        }
+    } else if args.len() == 2 && args[1] == "focus" {
+        match focus::focused_window() {
+            Ok(info) => println!("{}", serde_json::to_string(&info).unwrap()),
+            Err(e) => {
+                eprintln!("Focus command failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+    } else if args.len() > 1 && args[1] == "permissions" {
+        if let Err(e) = run_permissions(&args[2..]) {
+            eprintln!("Permissions command failed: {}", e);
+            std::process::exit(1);
+        }
+    } else if args.len() > 1 && args[1] == "doctor" {
+        if let Err(e) = doctor::run(&args[2..]) {
+            eprintln!("Doctor command failed: {}", e);
+            std::process::exit(1);
+        }
+    } else if args.len() > 2 && args[1] == "press" {
+        let combo = match press::parse_combo(&args[2]) {
+            Ok(combo) => combo,
+            Err(e) => {
+                eprintln!("Press command failed: {}", e);
+                std::process::exit(1);
+            }
+        };
+
+        match press::press_combo(&combo) {
+            Ok(_) => {
+                std::process::exit(0);
+            },
+            Err(e) => {
+                eprintln!("Press command failed: {}", e);
                 std::process::exit(101);
             }
         }
     } else {
-        let name = args.get(0).map(|s| s.as_str()).unwrap_or("speakmcp-rs");
-        eprintln!("Usage: {} [listen|write <text>]", name);
+        let name = args.first().map(|s| s.as_str()).unwrap_or("speakmcp-rs");
+        eprintln!("Usage: {} <command> [options]", name);
         eprintln!("Commands:");
-        eprintln!("  listen       - Listen for keyboard events");
-        eprintln!("  write <text> - Write text using accessibility API");
+        eprintln!("  listen        - Listen for keyboard events");
+        eprintln!("      --config <file>     Read these options from a JSON file; SIGHUP reloads it");
+        eprintln!("      --mouse             Also emit mouse events");
+        eprintln!("      --keys <names>      Only emit these keys (comma-separated) plus modifiers");
+        eprintln!("      --holds             Emit HoldStart/HoldEnd/Chord events (--hold-ms <n> sets the delay)");
+        eprintln!("      --grab <combos>     Keep these hotkey combos from reaching other apps (Linux only)");
+        eprintln!("      --layout <layout>   Keyboard layout for layout_key/char fields, e.g. fr or de:nodeadkeys (Linux only)");
+        eprintln!("      --device <device>   Only listen to this device, by name or /dev/input path; repeatable (Linux only)");
+        eprintln!("      --exclude <device>  Never listen to this device; repeatable (Linux only)");
+        eprintln!("                          Defaults: SPEAKMCP_INPUT_DEVICES / SPEAKMCP_EXCLUDE_INPUT_DEVICES, comma-separated");
+        eprintln!("      --log-file <path>   Also append events to this JSONL file");
+        eprintln!("      --rotate-size <n>   Rotate the log file at this size, e.g. 10M (default 10M, 3 files kept)");
+        eprintln!("      --takeover          Stop an already running listener first");
+        eprintln!("      --format <format>   json (default) or msgpack: 4-byte big-endian length + MessagePack map per message");
+        eprintln!("  write <text>  - Write text using accessibility API");
+        eprintln!("      --stdin             Read the text from stdin instead, verbatim");
+        eprintln!("      --file <path>       Read the text from a file instead, verbatim");
+        eprintln!("      --method <method>   type (default) or clipboard");
+        eprintln!("      --backend <backend> auto (default), enigo, uinput or wayland (Linux) for the type method");
+        eprintln!("      --delay-ms <n>      Delay between typed characters");
+        eprintln!("      --chunk-size <n>    Characters typed per chunk (0: no chunking)");
+        eprintln!("      --clipboard-fallback  Paste through the clipboard if typing fails");
+        eprintln!("  focus         - Report the focused app, window title and whether focus is in a password field, as JSON");
+        eprintln!("  press <combo> - Press a key combination, e.g. ctrl+shift+v or enter");
+        eprintln!("  doctor        - Check input permissions and report them as JSON");
+        eprintln!("      --crashes           List saved crash reports");
+        eprintln!("  permissions   - Report Accessibility/Input Monitoring access as JSON (macOS only)");
+        eprintln!("      --prompt            Show the system prompts for missing permissions");
+        eprintln!("  serve         - Read JSON commands from stdin (write, press, listen_start, listen_stop, reload_config, shutdown)");
+        eprintln!("      --takeover          Stop an already running listener when listen_start starts this one");
         std::process::exit(1);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<(WriteOptions, String), String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        parse_write_args(&args)
+    }
+
+    fn parse_ok(args: &[&str]) -> (WriteOptions, String) {
+        match parse(args) {
+            Ok(parsed) => parsed,
+            Err(e) => panic!("expected text, got error {e:?}"),
+        }
+    }
+
+    fn parse_err(args: &[&str]) -> String {
+        match parse(args) {
+            Ok((_, text)) => panic!("expected an error, got text {text:?}"),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn write_takes_the_text_after_its_options() {
+        let (options, text) = parse_ok(&["hello world"]);
+        assert_eq!(text, "hello world");
+        assert!(matches!(options.method, WriteMethod::Type));
+        assert_eq!(options.chunk_size, DEFAULT_CHUNK_SIZE);
+
+        let args = ["--method", "clipboard", "--backend", "enigo", "--delay-ms", "5", "--chunk-size", "0", "hi"];
+        let (options, text) = parse_ok(&args);
+        assert_eq!(text, "hi");
+        assert!(matches!(options.method, WriteMethod::Clipboard));
+        assert!(matches!(options.backend, WriteBackend::Enigo));
+        assert_eq!((options.delay_ms, options.chunk_size), (5, 0));
+        assert!(!options.clipboard_fallback);
+
+        let (options, _) = parse_ok(&["--clipboard-fallback", "hi"]);
+        assert!(options.clipboard_fallback);
+    }
+
+    #[test]
+    fn write_double_dash_ends_options() {
+        assert_eq!(parse_ok(&["--", "--stdin"]).1, "--stdin");
+        assert_eq!(parse_ok(&["--delay-ms", "1", "--", "--"]).1, "--");
+    }
+
+    #[test]
+    fn write_reads_files_verbatim() {
+        let path = std::env::temp_dir().join(format!("speakmcp-rs-write-test-{}", std::process::id()));
+        std::fs::write(&path, "line one\r\n\"quoted\"\n").unwrap();
+        let result = parse(&["--file", path.to_str().unwrap()]);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(result.map(|(_, text)| text), Ok("line one\r\n\"quoted\"\n".to_string()));
+    }
+
+    #[test]
+    fn write_rejects_text_with_stdin_or_file() {
+        assert!(parse_err(&["--stdin", "text"]).starts_with("Unexpected argument"));
+        assert!(parse_err(&["--file", "a", "text"]).starts_with("Unexpected argument"));
+        assert!(parse_err(&["--stdin", "--file", "a"]).starts_with("Only one of"));
+        assert!(parse_err(&["--stdin", "--stdin"]).starts_with("Only one of"));
+    }
+
+    #[test]
+    fn write_rejects_bad_options() {
+        assert_eq!(parse_err(&[]), "Missing text to write");
+        assert_eq!(parse_err(&["--"]), "Missing text to write");
+        assert_eq!(parse_err(&["--file"]), "--file requires a path");
+        assert_eq!(parse_err(&["--delay-ms", "soon", "hi"]), "Invalid --delay-ms value 'soon'");
+        assert!(parse_err(&["--method", "shout", "hi"]).starts_with("Unknown write method"));
+        assert!(parse_err(&["--backend", "x11", "hi"]).starts_with("Unknown write backend"));
+    }
+
+    fn listen(args: &[&str]) -> Result<ListenOptions, String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        parse_listen_args(&args)
+    }
+
+    fn listen_ok(args: &[&str]) -> ListenOptions {
+        match listen(args) {
+            Ok(options) => options,
+            Err(e) => panic!("expected options, got error {e:?}"),
+        }
+    }
+
+    fn listen_err(args: &[&str]) -> String {
+        match listen(args) {
+            Ok(_) => panic!("expected an error for {args:?}"),
+            Err(e) => e,
+        }
+    }
+
+    fn strings(values: &[&str]) -> Option<Vec<String>> {
+        Some(values.iter().map(|value| value.to_string()).collect())
+    }
+
+    #[test]
+    fn listen_flags_set_options() {
+        let options = listen_ok(&[]);
+        assert!(!options.mouse && options.keys.is_none() && options.hold_ms.is_none());
+
+        let options = listen_ok(&[
+            "--mouse", "--keys", "KeyA, Space,", "--grab", "ControlLeft+KeyM,Fn", "--layout", "fr:bepo",
+            "--log-file", "events.jsonl", "--rotate-size", "1K",
+        ]);
+        assert!(options.mouse);
+        assert_eq!(options.keys, strings(&["KeyA", "Space"]));
+        assert_eq!(options.grab, strings(&["ControlLeft+KeyM", "Fn"]));
+        assert_eq!(options.layout.as_deref(), Some("fr:bepo"));
+        assert_eq!(options.log_file.as_deref(), Some("events.jsonl"));
+        assert_eq!(options.rotate_size, Some(1024));
+    }
+
+    #[test]
+    fn listen_holds_and_hold_ms() {
+        assert_eq!(listen_ok(&["--holds"]).hold_ms, Some(holds::DEFAULT_HOLD_DELAY_MS));
+        assert_eq!(listen_ok(&["--hold-ms", "400"]).hold_ms, Some(400));
+        // --holds only turns holds on; it never resets a delay given before or after it
+        assert_eq!(listen_ok(&["--hold-ms", "400", "--holds"]).hold_ms, Some(400));
+        assert_eq!(listen_ok(&["--holds", "--hold-ms", "400"]).hold_ms, Some(400));
+        assert_eq!(listen_ok(&["--hold-ms", "400", "--hold-ms", "300"]).hold_ms, Some(300));
+    }
+
+    #[test]
+    fn listen_repeated_device_flags_accumulate() {
+        let options = listen_ok(&["--device", "/dev/input/event3", "--device", "Keychron", "--exclude", "yubikey"]);
+        assert_eq!(options.devices, strings(&["/dev/input/event3", "Keychron"]));
+        assert_eq!(options.exclude, strings(&["yubikey"]));
+        // Other repeated flags: the last one wins
+        assert_eq!(listen_ok(&["--keys", "KeyA", "--keys", "KeyB"]).keys, strings(&["KeyB"]));
+    }
+
+    #[test]
+    fn listen_flags_override_the_config_file() {
+        let path = std::env::temp_dir().join(format!("speakmcp-rs-listen-test-{}.json", std::process::id()));
+        let config = json!({
+            "mouse": true,
+            "keys": ["KeyA"],
+            "hold_ms": 500,
+            "devices": ["Keychron"],
+            "exclude": ["yubikey"],
+            "layout": "de",
+        });
+        std::fs::write(&path, config.to_string()).unwrap();
+        let path = path.to_str().unwrap().to_string();
+
+        let from_config = listen(&["--config", &path]);
+        // The config can come anywhere on the command line; flags win wherever they are
+        let merged = listen(&["--keys", "KeyB", "--holds", "--config", &path, "--device", "/dev/input/event3"]);
+        let with_hold_ms = listen(&["--config", &path, "--hold-ms", "300"]);
+        std::fs::remove_file(&path).unwrap();
+
+        let options = from_config.unwrap_or_else(|e| panic!("{e}"));
+        assert!(options.mouse);
+        assert_eq!(options.keys, strings(&["KeyA"]));
+        assert_eq!(options.hold_ms, Some(500));
+        assert_eq!(options.devices, strings(&["Keychron"]));
+
+        let options = merged.unwrap_or_else(|e| panic!("{e}"));
+        assert!(options.mouse);
+        assert_eq!(options.keys, strings(&["KeyB"]));
+        assert_eq!(options.hold_ms, Some(500));
+        assert_eq!(options.devices, strings(&["/dev/input/event3"]));
+        assert_eq!(options.exclude, strings(&["yubikey"]));
+        assert_eq!(options.layout.as_deref(), Some("de"));
+
+        assert_eq!(with_hold_ms.unwrap_or_else(|e| panic!("{e}")).hold_ms, Some(300));
+    }
+
+    #[test]
+    fn listen_rejects_bad_values() {
+        let cases: &[(&[&str], &str)] = &[
+            (&["--config"], "--config requires a path"),
+            (&["--keys"], "--keys requires a comma-separated list of key names"),
+            (&["--keys", " , "], "--keys requires at least one key name"),
+            (&["--hold-ms", "0"], "Invalid --hold-ms value '0'"),
+            (&["--hold-ms", "soon"], "Invalid --hold-ms value 'soon'"),
+            (&["--hold-ms"], "--hold-ms requires a value"),
+            (&["--grab"], "--grab requires a comma-separated list of key combos"),
+            (&["--device"], "--device requires a device name or path"),
+            (&["--exclude"], "--exclude requires a device name or path"),
+            (&["--rotate-size", "10MB"], "Invalid size '10MB'"),
+            (&["--verbose"], "Unknown listen option '--verbose'"),
+        ];
+        for (args, error) in cases {
+            assert_eq!(listen_err(args), *error, "for {:?}", args);
+        }
+        assert!(listen_err(&["--config", "/nonexistent/speakmcp.json"]).starts_with("Cannot read config"));
+    }
+
+    #[test]
+    fn listen_rejects_an_invalid_config_file() {
+        let path = std::env::temp_dir().join(format!("speakmcp-rs-bad-config-test-{}.json", std::process::id()));
+        std::fs::write(&path, r#"{"hold_ms": "soon"}"#).unwrap();
+        let result = listen(&["--config", path.to_str().unwrap()]);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(e) if e.starts_with("Invalid config")));
+    }
+}
@@ -0,0 +1,100 @@
+//! stdout framing for `listen`/`serve`
+//! JSON lines by default. `--format msgpack` instead writes each message as a
+//! 4-byte big-endian length followed by a MessagePack map with the same
+//! fields, and frames are batched: a writer thread drains everything queued
+//! since its last write in one go, so bursts of key events cost one syscall.
+
+use serde::Serialize;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex, Once};
+
+static MSGPACK: AtomicBool = AtomicBool::new(false);
+
+/// Set once the host has closed stdout; nothing is written after that
+static CLOSED: AtomicBool = AtomicBool::new(false);
+
+/// Encoded frames not yet written to stdout
+static PENDING: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+static WAKE: Condvar = Condvar::new();
+static WRITER: Once = Once::new();
+
+/// Select the output format: "json" (default) or "msgpack"
+pub fn set_format(format: &str) -> Result<(), String> {
+    match format {
+        "json" => MSGPACK.store(false, Ordering::SeqCst),
+        "msgpack" => {
+            MSGPACK.store(true, Ordering::SeqCst);
+            WRITER.call_once(|| {
+                std::thread::spawn(run_writer);
+            });
+        }
+        _ => return Err(format!("Unknown format '{}', expected json or msgpack", format)),
+    }
+    Ok(())
+}
+
+/// A write to stdout failed. EPIPE means the host went away and nobody is
+/// left to report to, so shut down rather than panic on the next write.
+fn write_failed(error: std::io::Error) {
+    if error.kind() != std::io::ErrorKind::BrokenPipe {
+        eprintln!("!error: failed to write to stdout: {}", error);
+    } else if !CLOSED.swap(true, Ordering::SeqCst) {
+        crate::shutdown();
+    }
+}
+
+/// Write one message to stdout in the selected format
+pub fn emit<T: Serialize>(message: &T) {
+    if CLOSED.load(Ordering::SeqCst) {
+        return;
+    }
+    if !MSGPACK.load(Ordering::Relaxed) {
+        let line = serde_json::to_string(message).unwrap();
+        let result = writeln!(std::io::stdout().lock(), "{}", line);
+        if let Err(e) = result {
+            write_failed(e);
+        }
+        return;
+    }
+
+    let body = match rmp_serde::to_vec_named(message) {
+        Ok(body) => body,
+        Err(e) => {
+            eprintln!("!error: failed to encode message: {}", e);
+            return;
+        }
+    };
+    let mut pending = PENDING.lock().unwrap();
+    pending.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    pending.extend_from_slice(&body);
+    WAKE.notify_one();
+}
+
+/// Write out everything queued so far
+/// stdout is locked before taking the queue so frames can't be reordered
+/// between this and the writer thread
+pub fn flush() {
+    if CLOSED.load(Ordering::SeqCst) {
+        return;
+    }
+    let mut stdout = std::io::stdout().lock();
+    let frames = std::mem::take(&mut *PENDING.lock().unwrap());
+    let result = stdout.write_all(&frames).and_then(|()| stdout.flush());
+    drop(stdout);
+    if let Err(e) = result {
+        write_failed(e);
+    }
+}
+
+fn run_writer() {
+    loop {
+        {
+            let mut pending = PENDING.lock().unwrap();
+            while pending.is_empty() {
+                pending = WAKE.wait(pending).unwrap();
+            }
+        }
+        flush();
+    }
+}
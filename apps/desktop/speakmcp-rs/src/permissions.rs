@@ -0,0 +1,87 @@
+//! macOS privacy permissions
+//! Listening needs Input Monitoring and typing needs Accessibility. Without
+//! them rdev just never sees an event, so `permissions` lets the host check
+//! (and ask for) both before starting the listener. The grants belong to the
+//! app that launched this binary, so prompts name the desktop app.
+
+use std::ffi::c_void;
+
+type CFTypeRef = *const c_void;
+
+#[link(name = "ApplicationServices", kind = "framework")]
+extern "C" {
+    static kAXTrustedCheckOptionPrompt: CFTypeRef;
+    fn AXIsProcessTrusted() -> bool;
+    fn AXIsProcessTrustedWithOptions(options: CFTypeRef) -> bool;
+}
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGPreflightListenEventAccess() -> bool;
+    fn CGRequestListenEventAccess() -> bool;
+    fn CGPreflightPostEventAccess() -> bool;
+}
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    static kCFBooleanTrue: CFTypeRef;
+    // Only their addresses are used, so the callback structs stay opaque
+    static kCFTypeDictionaryKeyCallBacks: u8;
+    static kCFTypeDictionaryValueCallBacks: u8;
+    fn CFDictionaryCreate(
+        allocator: CFTypeRef,
+        keys: *const CFTypeRef,
+        values: *const CFTypeRef,
+        count: isize,
+        key_callbacks: *const c_void,
+        value_callbacks: *const c_void,
+    ) -> CFTypeRef;
+    fn CFRelease(cf: CFTypeRef);
+}
+
+/// Current permission state
+pub struct Permissions {
+    /// Accessibility, needed to type and press keys
+    pub accessibility: bool,
+    /// Input Monitoring, needed to listen for keys
+    pub input_monitoring: bool,
+    /// Posting synthetic events, granted along with Accessibility
+    pub post_events: bool,
+}
+
+pub fn query() -> Permissions {
+    unsafe {
+        Permissions {
+            accessibility: AXIsProcessTrusted(),
+            input_monitoring: CGPreflightListenEventAccess(),
+            post_events: CGPreflightPostEventAccess(),
+        }
+    }
+}
+
+/// Show the system prompts for whatever is missing
+/// macOS only prompts once per app; after that the user has to go to
+/// System Settings, which the prompts themselves offer to open
+pub fn request() -> Permissions {
+    let current = query();
+    unsafe {
+        if !current.accessibility {
+            let keys = [kAXTrustedCheckOptionPrompt];
+            let values = [kCFBooleanTrue];
+            let options = CFDictionaryCreate(
+                std::ptr::null(),
+                keys.as_ptr(),
+                values.as_ptr(),
+                1,
+                &kCFTypeDictionaryKeyCallBacks as *const u8 as *const c_void,
+                &kCFTypeDictionaryValueCallBacks as *const u8 as *const c_void,
+            );
+            AXIsProcessTrustedWithOptions(options);
+            CFRelease(options);
+        }
+        if !current.input_monitoring {
+            CGRequestListenEventAccess();
+        }
+    }
+    query()
+}
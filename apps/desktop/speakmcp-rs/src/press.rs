@@ -0,0 +1,196 @@
+//! Key-combo injection for `press <combo>`
+//! Combos are modifier names and a key joined with '+', e.g. "ctrl+shift+v",
+//! "cmd+space" or just "enter". Names are case-insensitive.
+
+use enigo::{Direction, Enigo, Key, Keyboard, Settings};
+
+/// Modifiers held down while the main key is clicked
+#[derive(Debug)]
+pub struct KeyCombo {
+    modifiers: Vec<Key>,
+    key: Key,
+}
+
+fn parse_modifier(name: &str) -> Option<Key> {
+    match name {
+        "ctrl" | "control" => Some(Key::Control),
+        "shift" => Some(Key::Shift),
+        "alt" | "option" | "opt" => Some(Key::Alt),
+        "meta" | "cmd" | "command" | "super" | "win" => Some(Key::Meta),
+        _ => None,
+    }
+}
+
+fn parse_key(name: &str) -> Option<Key> {
+    if let Some(modifier) = parse_modifier(name) {
+        return Some(modifier);
+    }
+
+    let key = match name {
+        "enter" | "return" => Key::Return,
+        "tab" => Key::Tab,
+        "space" => Key::Space,
+        "esc" | "escape" => Key::Escape,
+        "backspace" => Key::Backspace,
+        "delete" | "del" => Key::Delete,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        "up" => Key::UpArrow,
+        "down" => Key::DownArrow,
+        "left" => Key::LeftArrow,
+        "right" => Key::RightArrow,
+        "capslock" => Key::CapsLock,
+        "volumeup" => Key::VolumeUp,
+        "volumedown" => Key::VolumeDown,
+        "mute" => Key::VolumeMute,
+        "playpause" => Key::MediaPlayPause,
+        "next" => Key::MediaNextTrack,
+        "prev" | "previous" => Key::MediaPrevTrack,
+        #[cfg(not(target_os = "macos"))]
+        "insert" | "ins" => Key::Insert,
+        #[cfg(not(target_os = "macos"))]
+        "printscreen" | "print" => Key::PrintScr,
+        #[cfg(not(target_os = "macos"))]
+        "pause" => Key::Pause,
+        #[cfg(not(target_os = "macos"))]
+        "numlock" => Key::Numlock,
+        "f1" => Key::F1,
+        "f2" => Key::F2,
+        "f3" => Key::F3,
+        "f4" => Key::F4,
+        "f5" => Key::F5,
+        "f6" => Key::F6,
+        "f7" => Key::F7,
+        "f8" => Key::F8,
+        "f9" => Key::F9,
+        "f10" => Key::F10,
+        "f11" => Key::F11,
+        "f12" => Key::F12,
+        "f13" => Key::F13,
+        "f14" => Key::F14,
+        "f15" => Key::F15,
+        "f16" => Key::F16,
+        "f17" => Key::F17,
+        "f18" => Key::F18,
+        "f19" => Key::F19,
+        "f20" => Key::F20,
+        _ => {
+            // Any other single character is sent as-is, e.g. "ctrl+v" or "ctrl+/"
+            let mut chars = name.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Key::Unicode(c),
+                _ => return None,
+            }
+        }
+    };
+    Some(key)
+}
+
+/// Parse a combo like "ctrl+shift+v"
+pub fn parse_combo(combo: &str) -> Result<KeyCombo, String> {
+    let combo = combo.trim().to_lowercase();
+
+    // A trailing "++" means the key itself is '+', e.g. "ctrl++"
+    let (rest, key_name) = if combo == "+" {
+        ("", "+")
+    } else if let Some(rest) = combo.strip_suffix("++") {
+        (rest, "+")
+    } else {
+        match combo.rsplit_once('+') {
+            Some((rest, key)) => (rest, key),
+            None => ("", combo.as_str()),
+        }
+    };
+
+    let key_name = key_name.trim();
+    if key_name.is_empty() {
+        return Err(format!("Missing key in combo '{}'", combo));
+    }
+    let key = parse_key(key_name).ok_or_else(|| format!("Unknown key '{}'", key_name))?;
+
+    let mut modifiers = Vec::new();
+    if !rest.is_empty() {
+        for name in rest.split('+').map(str::trim) {
+            let modifier = parse_modifier(name)
+                .ok_or_else(|| format!("Unknown modifier '{}' in combo '{}'", name, combo))?;
+            modifiers.push(modifier);
+        }
+    }
+
+    Ok(KeyCombo { modifiers, key })
+}
+
+/// Hold the modifiers, click the key, then release the modifiers in reverse order
+pub fn press_combo(combo: &KeyCombo) -> Result<(), Box<dyn std::error::Error>> {
+    let mut enigo = Enigo::new(&Settings::default())?;
+
+    let mut pressed = Vec::new();
+    let mut result = Ok(());
+    for modifier in &combo.modifiers {
+        if let Err(e) = enigo.key(*modifier, Direction::Press) {
+            result = Err(e);
+            break;
+        }
+        pressed.push(*modifier);
+    }
+    if result.is_ok() {
+        result = enigo.key(combo.key, Direction::Click);
+    }
+
+    // Always release what was pressed so no modifier is left stuck down
+    for modifier in pressed.iter().rev() {
+        if let Err(e) = enigo.key(*modifier, Direction::Release) {
+            eprintln!("Failed to release modifier {:?}: {}", modifier, e);
+        }
+    }
+
+    result?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(combo: &str) -> (Vec<Key>, Key) {
+        let combo = parse_combo(combo).unwrap();
+        (combo.modifiers, combo.key)
+    }
+
+    #[test]
+    fn parses_modifiers_and_key() {
+        assert_eq!(parse("ctrl+shift+v"), (vec![Key::Control, Key::Shift], Key::Unicode('v')));
+        assert_eq!(parse("cmd+space"), (vec![Key::Meta], Key::Space));
+        assert_eq!(parse("enter"), (vec![], Key::Return));
+    }
+
+    #[test]
+    fn ignores_case_and_whitespace() {
+        assert_eq!(parse(" Ctrl + Shift + V "), (vec![Key::Control, Key::Shift], Key::Unicode('v')));
+        assert_eq!(parse("ENTER"), (vec![], Key::Return));
+    }
+
+    #[test]
+    fn plus_as_the_key() {
+        assert_eq!(parse("+"), (vec![], Key::Unicode('+')));
+        assert_eq!(parse("ctrl++"), (vec![Key::Control], Key::Unicode('+')));
+        assert_eq!(parse("ctrl+shift++"), (vec![Key::Control, Key::Shift], Key::Unicode('+')));
+    }
+
+    #[test]
+    fn a_modifier_alone_is_the_key() {
+        assert_eq!(parse("shift"), (vec![], Key::Shift));
+        assert_eq!(parse("ctrl+alt"), (vec![Key::Control], Key::Alt));
+    }
+
+    #[test]
+    fn rejects_missing_or_unknown_keys() {
+        assert!(parse_combo("").unwrap_err().starts_with("Missing key"));
+        assert!(parse_combo("ctrl+").unwrap_err().starts_with("Missing key"));
+        assert!(parse_combo("ctrl+bogus").unwrap_err().starts_with("Unknown key"));
+        assert!(parse_combo("hyper+v").unwrap_err().starts_with("Unknown modifier"));
+        assert!(parse_combo("v+ctrl").unwrap_err().starts_with("Unknown modifier"));
+    }
+}
@@ -0,0 +1,186 @@
+//! uinput text injection for Linux
+//! Creates a virtual keyboard and types text as key presses, for Wayland
+//! compositors where enigo's injection doesn't work. Keycodes go through the
+//! compositor's active keymap, so characters have to be mapped through the
+//! user's layout (see `layout::Keymap::char_keys`); `us_keys` is the fixed
+//! US mapping, only used when libxkbcommon isn't installed to compile it.
+
+use crate::grab::VIRTUAL_DEVICE_NAME;
+use crate::WriteOptions;
+use evdev::uinput::{VirtualDevice, VirtualDeviceBuilder};
+use evdev::{AttributeSet, EventType, InputEvent, Key};
+use std::collections::HashMap;
+use std::thread;
+use std::time::Duration;
+
+/// Compositors need a moment to pick up a new input device before its
+/// first events are delivered
+const DEVICE_SETTLE_DELAY: Duration = Duration::from_millis(200);
+
+/// Which key, and whether Shift is needed, types each character
+pub type CharKeys = HashMap<char, (Key, bool)>;
+
+/// The key (and whether Shift is needed) for a character on a US layout
+fn char_to_key(c: char) -> Option<(Key, bool)> {
+    let unshifted = |key| Some((key, false));
+    let shifted = |key| Some((key, true));
+
+    if c.is_ascii_alphabetic() {
+        let key = LETTER_KEYS[(c.to_ascii_lowercase() as u8 - b'a') as usize];
+        return Some((key, c.is_ascii_uppercase()));
+    }
+
+    match c {
+        '0' => unshifted(Key::KEY_0),
+        '1' => unshifted(Key::KEY_1),
+        '2' => unshifted(Key::KEY_2),
+        '3' => unshifted(Key::KEY_3),
+        '4' => unshifted(Key::KEY_4),
+        '5' => unshifted(Key::KEY_5),
+        '6' => unshifted(Key::KEY_6),
+        '7' => unshifted(Key::KEY_7),
+        '8' => unshifted(Key::KEY_8),
+        '9' => unshifted(Key::KEY_9),
+        ')' => shifted(Key::KEY_0),
+        '!' => shifted(Key::KEY_1),
+        '@' => shifted(Key::KEY_2),
+        '#' => shifted(Key::KEY_3),
+        '$' => shifted(Key::KEY_4),
+        '%' => shifted(Key::KEY_5),
+        '^' => shifted(Key::KEY_6),
+        '&' => shifted(Key::KEY_7),
+        '*' => shifted(Key::KEY_8),
+        '(' => shifted(Key::KEY_9),
+        ' ' => unshifted(Key::KEY_SPACE),
+        '\n' => unshifted(Key::KEY_ENTER),
+        '\t' => unshifted(Key::KEY_TAB),
+        '-' => unshifted(Key::KEY_MINUS),
+        '_' => shifted(Key::KEY_MINUS),
+        '=' => unshifted(Key::KEY_EQUAL),
+        '+' => shifted(Key::KEY_EQUAL),
+        '[' => unshifted(Key::KEY_LEFTBRACE),
+        '{' => shifted(Key::KEY_LEFTBRACE),
+        ']' => unshifted(Key::KEY_RIGHTBRACE),
+        '}' => shifted(Key::KEY_RIGHTBRACE),
+        '\\' => unshifted(Key::KEY_BACKSLASH),
+        '|' => shifted(Key::KEY_BACKSLASH),
+        ';' => unshifted(Key::KEY_SEMICOLON),
+        ':' => shifted(Key::KEY_SEMICOLON),
+        '\'' => unshifted(Key::KEY_APOSTROPHE),
+        '"' => shifted(Key::KEY_APOSTROPHE),
+        '`' => unshifted(Key::KEY_GRAVE),
+        '~' => shifted(Key::KEY_GRAVE),
+        ',' => unshifted(Key::KEY_COMMA),
+        '<' => shifted(Key::KEY_COMMA),
+        '.' => unshifted(Key::KEY_DOT),
+        '>' => shifted(Key::KEY_DOT),
+        '/' => unshifted(Key::KEY_SLASH),
+        '?' => shifted(Key::KEY_SLASH),
+        _ => None,
+    }
+}
+
+const LETTER_KEYS: [Key; 26] = [
+    Key::KEY_A, Key::KEY_B, Key::KEY_C, Key::KEY_D, Key::KEY_E, Key::KEY_F, Key::KEY_G,
+    Key::KEY_H, Key::KEY_I, Key::KEY_J, Key::KEY_K, Key::KEY_L, Key::KEY_M, Key::KEY_N,
+    Key::KEY_O, Key::KEY_P, Key::KEY_Q, Key::KEY_R, Key::KEY_S, Key::KEY_T, Key::KEY_U,
+    Key::KEY_V, Key::KEY_W, Key::KEY_X, Key::KEY_Y, Key::KEY_Z,
+];
+
+/// Character mapping for a US layout
+pub fn us_keys() -> CharKeys {
+    (' '..='~')
+        .chain(['\n', '\t'])
+        .filter_map(|c| char_to_key(c).map(|key| (c, key)))
+        .collect()
+}
+
+/// Character mapping for a compiled XKB keymap
+pub fn layout_keys(keymap: &crate::layout::Keymap) -> CharKeys {
+    keymap
+        .char_keys()
+        .into_iter()
+        .map(|(c, (code, shift))| (c, (Key::new(code), shift)))
+        .collect()
+}
+
+/// Fail with the characters in `text` that no key in `keys` produces
+pub fn check_typeable(text: &str, keys: &CharKeys) -> Result<(), String> {
+    // '\r' is dropped so "\r\n" line endings type a single Enter
+    let untypeable: String = text.chars().filter(|&c| c != '\r' && !keys.contains_key(&c)).collect();
+    if untypeable.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("uinput backend can't type these characters: {}", untypeable))
+    }
+}
+
+/// Whether this looks like a Wayland session, where enigo injection is unreliable
+pub fn is_wayland_session() -> bool {
+    std::env::var_os("WAYLAND_DISPLAY").is_some()
+        || std::env::var("XDG_SESSION_TYPE").is_ok_and(|session| session == "wayland")
+}
+
+pub struct VirtualKeyboard {
+    device: VirtualDevice,
+    keys: CharKeys,
+    /// Bytes of the text given to the last write_text that were typed
+    typed: usize,
+}
+
+impl VirtualKeyboard {
+    /// Create a keyboard that types characters with the keys in `keys`
+    pub fn new(keys: CharKeys) -> std::io::Result<Self> {
+        let mut supported = AttributeSet::<Key>::new();
+        supported.insert(Key::KEY_LEFTSHIFT);
+        for (key, _) in keys.values() {
+            supported.insert(*key);
+        }
+
+        let device = VirtualDeviceBuilder::new()
+            .map_err(|e| std::io::Error::new(e.kind(), format!("Cannot open /dev/uinput: {}", e)))?
+            .name(VIRTUAL_DEVICE_NAME)
+            .with_keys(&supported)?
+            .build()?;
+        thread::sleep(DEVICE_SETTLE_DELAY);
+
+        Ok(VirtualKeyboard { device, keys, typed: 0 })
+    }
+
+    fn key(&mut self, key: Key, value: i32) -> std::io::Result<()> {
+        // Each press/release goes in its own frame; emit() adds the SYN_REPORT
+        self.device.emit(&[InputEvent::new(EventType::KEY, key.code(), value)])
+    }
+
+    fn type_str(&mut self, text: &str) -> std::io::Result<()> {
+        for c in text.chars() {
+            let Some(&(key, shift)) = self.keys.get(&c) else {
+                self.typed += c.len_utf8();
+                continue;
+            };
+            if shift {
+                self.key(Key::KEY_LEFTSHIFT, 1)?;
+            }
+            self.key(key, 1)?;
+            self.key(key, 0)?;
+            if shift {
+                self.key(Key::KEY_LEFTSHIFT, 0)?;
+            }
+            self.typed += c.len_utf8();
+        }
+        Ok(())
+    }
+
+    /// How much of the text given to the last write_text was typed, in bytes,
+    /// so a fallback can carry on from there
+    pub fn typed(&self) -> usize {
+        self.typed
+    }
+
+    pub fn write_text(&mut self, text: &str, options: &WriteOptions) -> Result<(), Box<dyn std::error::Error>> {
+        check_typeable(text, &self.keys)?;
+        self.typed = 0;
+        crate::type_in_chunks(text, options, |chunk| self.type_str(chunk))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,184 @@
+//! Wayland-native text injection via virtual-keyboard-unstable-v1
+//! For compositors that block uinput (or where /dev/uinput isn't writable),
+//! e.g. Sway and Hyprland. Like wtype, we upload a throwaway XKB keymap that
+//! maps a keycode to each distinct character being typed, so any Unicode
+//! text can be typed regardless of the user's layout.
+
+use crate::WriteOptions;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::io::Write;
+use std::os::fd::{AsFd, FromRawFd};
+use std::time::Instant;
+use wayland_client::globals::{registry_queue_init, GlobalListContents};
+use wayland_client::protocol::{wl_keyboard, wl_registry, wl_seat};
+use wayland_client::{delegate_noop, Connection, Dispatch, EventQueue, QueueHandle};
+use wayland_protocols_misc::zwp_virtual_keyboard_v1::client::{
+    zwp_virtual_keyboard_manager_v1::ZwpVirtualKeyboardManagerV1,
+    zwp_virtual_keyboard_v1::ZwpVirtualKeyboardV1,
+};
+
+/// Keycodes per uploaded keymap; longer texts upload a new keymap per segment
+const MAX_KEYMAP_KEYS: usize = 200;
+
+/// Keycodes sent on the wire are evdev codes; XKB keycodes are offset by 8
+const XKB_KEYCODE_OFFSET: u32 = 8;
+
+struct State;
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for State {
+    fn event(
+        _: &mut Self,
+        _: &wl_registry::WlRegistry,
+        _: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+delegate_noop!(State: ignore wl_seat::WlSeat);
+delegate_noop!(State: ZwpVirtualKeyboardManagerV1);
+delegate_noop!(State: ZwpVirtualKeyboardV1);
+
+/// XKB keysym name for a character
+fn keysym_name(c: char) -> String {
+    match c {
+        '\n' => "Return".to_string(),
+        '\t' => "Tab".to_string(),
+        _ => format!("U{:04X}", c as u32),
+    }
+}
+
+/// Build a keymap assigning evdev keycodes 1..=n to `chars`
+fn build_keymap(chars: &[char]) -> String {
+    let mut keycodes = String::new();
+    let mut symbols = String::new();
+    for (index, c) in chars.iter().enumerate() {
+        let xkb_keycode = index as u32 + 1 + XKB_KEYCODE_OFFSET;
+        keycodes.push_str(&format!("<K{}> = {};\n", index + 1, xkb_keycode));
+        symbols.push_str(&format!("key <K{}> {{[{}]}};\n", index + 1, keysym_name(*c)));
+    }
+
+    format!(
+        "xkb_keymap {{\n\
+         xkb_keycodes \"(unnamed)\" {{\nminimum = 8;\nmaximum = {};\n{}}};\n\
+         xkb_types \"(unnamed)\" {{ include \"complete\" }};\n\
+         xkb_compatibility \"(unnamed)\" {{ include \"complete\" }};\n\
+         xkb_symbols \"(unnamed)\" {{\n{}}};\n\
+         }};\n",
+        chars.len() as u32 + 1 + XKB_KEYCODE_OFFSET,
+        keycodes,
+        symbols
+    )
+}
+
+pub struct VirtualKeyboard {
+    event_queue: EventQueue<State>,
+    keyboard: ZwpVirtualKeyboardV1,
+    started: Instant,
+    /// Bytes of the text given to the last write_text that were typed
+    typed: usize,
+}
+
+impl VirtualKeyboard {
+    /// Connect to the compositor and create a virtual keyboard on the first seat
+    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        let conn = Connection::connect_to_env()
+            .map_err(|e| format!("Cannot connect to Wayland compositor: {}", e))?;
+        let (globals, event_queue) = registry_queue_init::<State>(&conn)?;
+        let qh = event_queue.handle();
+
+        let seat: wl_seat::WlSeat = globals
+            .bind(&qh, 1..=7, ())
+            .map_err(|_| "Wayland compositor has no seat")?;
+        let manager: ZwpVirtualKeyboardManagerV1 = globals
+            .bind(&qh, 1..=1, ())
+            .map_err(|_| "Wayland compositor doesn't support virtual-keyboard-unstable-v1")?;
+        let keyboard = manager.create_virtual_keyboard(&seat, &qh, ());
+
+        Ok(VirtualKeyboard { event_queue, keyboard, started: Instant::now(), typed: 0 })
+    }
+
+    fn upload_keymap(&mut self, chars: &[char]) -> Result<(), Box<dyn std::error::Error>> {
+        let keymap = build_keymap(chars);
+
+        // The keymap is passed as a file descriptor; like wtype, back it with an
+        // anonymous memfd so nothing touches the filesystem
+        let fd = unsafe { libc::memfd_create(c"speakmcp-rs-keymap".as_ptr(), libc::MFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(format!("Cannot create keymap memfd: {}", std::io::Error::last_os_error()).into());
+        }
+        let mut file = unsafe { std::fs::File::from_raw_fd(fd) };
+        file.write_all(keymap.as_bytes())?;
+        file.write_all(&[0])?;
+        file.flush()?;
+
+        self.keyboard.keymap(
+            wl_keyboard::KeymapFormat::XkbV1.into(),
+            file.as_fd(),
+            keymap.len() as u32 + 1,
+        );
+        self.event_queue.roundtrip(&mut State)?;
+        Ok(())
+    }
+
+    fn key(&self, keycode: u32, pressed: bool) {
+        let time = self.started.elapsed().as_millis() as u32;
+        let state = if pressed { wl_keyboard::KeyState::Pressed } else { wl_keyboard::KeyState::Released };
+        self.keyboard.key(time, keycode, state.into());
+    }
+
+    fn type_str(&mut self, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+        // Each character with the offset just past it in `text`
+        let chars: Vec<(char, usize)> = text
+            .char_indices()
+            .filter(|&(_, c)| c != '\r')
+            .map(|(i, c)| (c, i + c.len_utf8()))
+            .collect();
+        let mut start = 0;
+        let mut done = 0;
+
+        while start < chars.len() {
+            // Take as many characters as fit in one keymap
+            let mut keycodes: HashMap<char, u32> = HashMap::new();
+            let mut keymap_chars = Vec::new();
+            let mut end = start;
+            while end < chars.len() {
+                let c = chars[end].0;
+                if let Entry::Vacant(entry) = keycodes.entry(c) {
+                    if keymap_chars.len() == MAX_KEYMAP_KEYS {
+                        break;
+                    }
+                    keymap_chars.push(c);
+                    entry.insert(keymap_chars.len() as u32);
+                }
+                end += 1;
+            }
+
+            self.upload_keymap(&keymap_chars)?;
+            for (c, _) in &chars[start..end] {
+                self.key(keycodes[c], true);
+                self.key(keycodes[c], false);
+            }
+            self.event_queue.roundtrip(&mut State)?;
+            self.typed += chars[end - 1].1 - done;
+            done = chars[end - 1].1;
+            start = end;
+        }
+        self.typed += text.len() - done;
+        Ok(())
+    }
+
+    /// How much of the text given to the last write_text was typed, in bytes,
+    /// so a fallback can carry on from there
+    pub fn typed(&self) -> usize {
+        self.typed
+    }
+
+    pub fn write_text(&mut self, text: &str, options: &WriteOptions) -> Result<(), Box<dyn std::error::Error>> {
+        self.typed = 0;
+        crate::type_in_chunks(text, options, |chunk| self.type_str(chunk))
+    }
+}
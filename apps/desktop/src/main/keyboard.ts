@@ -26,6 +26,10 @@ const rdevPath = path
   )
   .replace("app.asar", "app.asar.unpacked")
 
+// APP_ID is a build-time define, not a real environment variable, so pass it
+// on explicitly; speakmcp-rs uses it to find the data folder for crash reports
+const rdevEnv = () => ({ ...process.env, APP_ID: process.env.APP_ID })
+
 type RdevEvent = {
   event_type: "KeyPress" | "KeyRelease"
   data: {
@@ -47,7 +51,13 @@ type RdevEvent = {
 
 export const writeText = (text: string) => {
   return new Promise<void>((resolve, reject) => {
-    const child: ChildProcess = spawn(rdevPath, ["write", text])
+    // Pass the text on stdin: argv has length limits and mangles newlines/quotes on Windows
+    const child: ChildProcess = spawn(rdevPath, ["write", "--stdin"], { env: rdevEnv() })
+    // EPIPE here means the helper failed to spawn or exited early; without a
+    // listener Node would throw it as an uncaught exception. The "error" or
+    // "close" handler below reports the failure, with the exit code and stderr.
+    child.stdin?.on("error", () => {})
+    child.stdin?.end(text)
 
     // Register process if agent mode is active
     if (state.isAgentModeActive) {
@@ -81,7 +91,7 @@ export const writeText = (text: string) => {
 
 export const getFocusedAppInfo = () => {
   return new Promise<string>((resolve, reject) => {
-    const child: ChildProcess = spawn(rdevPath, ["get-focus"])
+    const child: ChildProcess = spawn(rdevPath, ["get-focus"], { env: rdevEnv() })
 
     // Register process if agent mode is active
     if (state.isAgentModeActive) {
@@ -116,7 +126,7 @@ export const getFocusedAppInfo = () => {
 
 export const restoreFocusToApp = (appInfo: string) => {
   return new Promise<void>((resolve, reject) => {
-    const child: ChildProcess = spawn(rdevPath, ["restore-focus", appInfo])
+    const child: ChildProcess = spawn(rdevPath, ["restore-focus", appInfo], { env: rdevEnv() })
 
     // Register process if agent mode is active
     if (state.isAgentModeActive) {
@@ -1339,7 +1349,7 @@ export function listenToKeyboardEvents() {
     }
   }
 
-  const child = spawn(rdevPath, ["listen"], {})
+  const child = spawn(rdevPath, ["listen", "--takeover"], { env: rdevEnv() })
 
   if (isDebugKeybinds()) {
     logKeybinds("Starting keyboard event listener with rdev path:", rdevPath)